@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+
+use tree::{levels, tree_size};
+use {Array, Result};
+
+/// Tree node addressed by its height (0 for leaves) and position within
+/// the level
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Node {
+    pub height: usize,
+    pub position: usize,
+}
+
+/// Request for hashes of the given nodes of a tree with `leaf_count` leaves
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NodeRequest {
+    pub leaf_count: usize,
+    pub nodes: Vec<Node>,
+}
+
+/// State of a diff between two trees of the same shape. Starting at the
+/// root, node hashes of both trees are compared level by level and only
+/// the children of differing nodes are requested next.
+#[derive(Clone, Debug)]
+pub struct TreeDiff {
+    leaf_count: usize,
+    level_lens: Vec<usize>,
+    pending: Vec<Node>,
+    leaves: Vec<usize>,
+}
+
+impl TreeDiff {
+    pub fn new(leaf_count: usize) -> Self {
+        let (_, height) = tree_size(leaf_count);
+        let level_lens = levels(leaf_count).iter().map(|level| level.len()).collect();
+        let pending = if leaf_count > 0 {
+            vec![Node {
+                height: height - 1,
+                position: 0,
+            }]
+        } else {
+            Vec::new()
+        };
+
+        TreeDiff {
+            leaf_count,
+            level_lens,
+            pending,
+            leaves: Vec::new(),
+        }
+    }
+
+    /// Nodes whose hashes need to be compared next
+    pub fn request(&self) -> NodeRequest {
+        NodeRequest {
+            leaf_count: self.leaf_count,
+            nodes: self.pending.clone(),
+        }
+    }
+
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Compare hashes of the pending nodes, as returned for `request` by
+    /// the local and the remote tree. Missing hashes are never equal.
+    pub fn update(&mut self, local: &[Option<Array>], remote: &[Option<Array>]) -> Result<()> {
+        if local.len() != self.pending.len() || remote.len() != self.pending.len() {
+            return err!(
+                "Invalid node hash count: expected {:?}, got {:?} and {:?}",
+                self.pending.len(),
+                local.len(),
+                remote.len()
+            );
+        }
+
+        let mut pending = Vec::new();
+
+        for (i, node) in self.pending.iter().enumerate() {
+            match (&local[i], &remote[i]) {
+                (Some(l), Some(r)) if l == r => continue,
+                _ => (),
+            }
+
+            if node.height == 0 {
+                self.leaves.push(node.position);
+                continue;
+            }
+
+            let height = node.height - 1;
+            for position in node.position * 2..node.position * 2 + 2 {
+                if position < self.level_lens[height] {
+                    pending.push(Node { height, position });
+                }
+            }
+        }
+
+        self.pending = pending;
+        Ok(())
+    }
+
+    /// Indices of differing leaves, in ascending order
+    pub fn leaves(&self) -> &[usize] {
+        &self.leaves
+    }
+}
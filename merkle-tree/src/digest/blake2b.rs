@@ -0,0 +1,49 @@
+use crypto;
+use crypto::digest::Digest as CryptoDigest;
+
+use digest::{Algorithm, Digest};
+use Array;
+
+const BLAKE2B_OUTPUT_LEN: usize = 256 / 8;
+
+/// BLAKE2b with a 256-bit output
+#[derive(Clone)]
+pub struct Blake2b {
+    ctx: crypto::blake2b::Blake2b,
+}
+
+impl Digest for Blake2b {
+    fn new() -> Self {
+        Blake2b {
+            ctx: crypto::blake2b::Blake2b::new(BLAKE2B_OUTPUT_LEN),
+        }
+    }
+
+    #[inline(always)]
+    fn algorithm() -> Algorithm {
+        Algorithm::Blake2b
+    }
+
+    #[inline(always)]
+    fn output_size() -> usize {
+        BLAKE2B_OUTPUT_LEN
+    }
+
+    #[inline]
+    fn input<A: AsRef<[u8]>>(&mut self, data: A) {
+        self.ctx.input(data.as_ref());
+    }
+
+    fn result(&mut self) -> Array {
+        let mut result = vec![0; BLAKE2B_OUTPUT_LEN];
+        self.ctx.result(&mut result);
+
+        self.reset();
+        result
+    }
+
+    #[inline]
+    fn reset(&mut self) {
+        self.ctx.reset();
+    }
+}
@@ -1,9 +1,57 @@
+pub mod blake2b;
+pub mod sha256;
+pub mod sha3;
 pub mod sha512;
 
+use serde::{Deserialize, Serialize};
 use Array;
 
+/// Identifies a `Digest` implementation, e.g. when persisted or exchanged
+/// with other nodes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Algorithm {
+    #[default]
+    Sha512,
+    Sha256,
+    Blake2b,
+    Sha3_256,
+}
+
+impl Algorithm {
+    /// Stable identifier used in binary encodings
+    pub fn id(&self) -> u8 {
+        match self {
+            Algorithm::Sha512 => 1,
+            Algorithm::Sha256 => 2,
+            Algorithm::Blake2b => 3,
+            Algorithm::Sha3_256 => 4,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Algorithm::Sha512),
+            2 => Some(Algorithm::Sha256),
+            3 => Some(Algorithm::Blake2b),
+            4 => Some(Algorithm::Sha3_256),
+            _ => None,
+        }
+    }
+
+    pub fn output_size(&self) -> usize {
+        match self {
+            Algorithm::Sha512 => <sha512::Sha512 as Digest>::output_size(),
+            Algorithm::Sha256 => <sha256::Sha256 as Digest>::output_size(),
+            Algorithm::Blake2b => <blake2b::Blake2b as Digest>::output_size(),
+            Algorithm::Sha3_256 => <sha3::Sha3_256 as Digest>::output_size(),
+        }
+    }
+}
+
 pub trait Digest {
     fn new() -> Self;
+    /// Return the algorithm identifier
+    fn algorithm() -> Algorithm;
     /// Return output size
     fn output_size() -> usize;
     /// Feed input data
@@ -13,3 +61,43 @@ pub trait Digest {
     /// Reset state
     fn reset(&mut self);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::blake2b::Blake2b;
+    use super::sha256::Sha256;
+    use super::sha3::Sha3_256;
+    use super::sha512::Sha512;
+    use super::*;
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn check<D: Digest>(expected: &str) {
+        let mut digest = D::new();
+
+        digest.input(b"a");
+        digest.input(b"bc");
+        let result = digest.result();
+
+        assert_eq!(result.len(), D::output_size());
+        assert_eq!(result.len(), D::algorithm().output_size());
+        assert_eq!(to_hex(&result), expected);
+
+        // state is reset after retrieving the result
+        digest.input(b"abc");
+        assert_eq!(to_hex(&digest.result()), expected);
+    }
+
+    #[test]
+    fn test_digests() {
+        check::<Sha512>(
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+        );
+        check::<Sha256>("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        check::<Blake2b>("bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319");
+        check::<Sha3_256>("3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532");
+    }
+}
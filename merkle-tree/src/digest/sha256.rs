@@ -0,0 +1,54 @@
+use digest::{Algorithm, Digest};
+use ring;
+use ring::digest::{SHA256, SHA256_OUTPUT_LEN};
+
+use Array;
+
+#[derive(Clone)]
+pub struct Sha256 {
+    ctx: Option<ring::digest::Context>,
+}
+
+impl Sha256 {
+    #[inline]
+    fn new_ctx() -> ring::digest::Context {
+        ring::digest::Context::new(&SHA256)
+    }
+}
+
+impl Digest for Sha256 {
+    fn new() -> Self {
+        Sha256 {
+            ctx: Some(Self::new_ctx()),
+        }
+    }
+
+    #[inline(always)]
+    fn algorithm() -> Algorithm {
+        Algorithm::Sha256
+    }
+
+    #[inline(always)]
+    fn output_size() -> usize {
+        SHA256_OUTPUT_LEN
+    }
+
+    #[inline]
+    fn input<A: AsRef<[u8]>>(&mut self, data: A) {
+        let ctx = self.ctx.as_mut().unwrap();
+        ctx.update(data.as_ref());
+    }
+
+    fn result(&mut self) -> Array {
+        let ctx = self.ctx.take().unwrap();
+        let digest = ctx.finish();
+
+        self.reset();
+        digest.as_ref().to_vec()
+    }
+
+    #[inline]
+    fn reset(&mut self) {
+        self.ctx = Some(Self::new_ctx());
+    }
+}
@@ -0,0 +1,48 @@
+use crypto;
+use crypto::digest::Digest as CryptoDigest;
+
+use digest::{Algorithm, Digest};
+use Array;
+
+const SHA3_256_OUTPUT_LEN: usize = 256 / 8;
+
+#[derive(Clone)]
+pub struct Sha3_256 {
+    ctx: crypto::sha3::Sha3,
+}
+
+impl Digest for Sha3_256 {
+    fn new() -> Self {
+        Sha3_256 {
+            ctx: crypto::sha3::Sha3::sha3_256(),
+        }
+    }
+
+    #[inline(always)]
+    fn algorithm() -> Algorithm {
+        Algorithm::Sha3_256
+    }
+
+    #[inline(always)]
+    fn output_size() -> usize {
+        SHA3_256_OUTPUT_LEN
+    }
+
+    #[inline]
+    fn input<A: AsRef<[u8]>>(&mut self, data: A) {
+        self.ctx.input(data.as_ref());
+    }
+
+    fn result(&mut self) -> Array {
+        let mut result = vec![0; SHA3_256_OUTPUT_LEN];
+        self.ctx.result(&mut result);
+
+        self.reset();
+        result
+    }
+
+    #[inline]
+    fn reset(&mut self) {
+        self.ctx.reset();
+    }
+}
@@ -1,4 +1,4 @@
-use digest::Digest;
+use digest::{Algorithm, Digest};
 use ring;
 use ring::digest::{SHA512, SHA512_OUTPUT_LEN};
 
@@ -23,6 +23,11 @@ impl Digest for Sha512 {
         }
     }
 
+    #[inline(always)]
+    fn algorithm() -> Algorithm {
+        Algorithm::Sha512
+    }
+
     #[inline(always)]
     fn output_size() -> usize {
         SHA512_OUTPUT_LEN
@@ -1,13 +1,19 @@
 extern crate bit_vec;
 extern crate bit_vec_serde;
+extern crate crypto;
 extern crate ring;
 extern crate serde;
 extern crate streaming_iterator;
 
+#[cfg(test)]
+extern crate rand;
+
 pub mod digest;
 #[macro_use]
 pub mod error;
+pub mod diff;
 pub mod level;
+pub mod mode;
 #[macro_use]
 pub mod proof;
 pub mod tree;
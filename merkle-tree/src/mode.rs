@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+
+use digest::Digest;
+use Array;
+
+/// Hashing scheme of tree leaves and nodes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mode {
+    /// Leaves are digests of raw data and nodes are digests of concatenated
+    /// children; a lone child is hashed on its own
+    #[default]
+    Plain,
+    /// RFC 6962 hashing: leaf and node inputs are prefixed with distinct
+    /// domain separators and a lone child is promoted to its parent
+    Rfc6962,
+}
+
+impl Mode {
+    pub const LEAF_PREFIX: u8 = 0x00;
+    pub const NODE_PREFIX: u8 = 0x01;
+
+    pub fn hash_leaf<D>(&self, digest: &mut D, data: &[u8]) -> Array
+    where
+        D: Digest,
+    {
+        if let Mode::Rfc6962 = self {
+            digest.input([Self::LEAF_PREFIX]);
+        }
+
+        digest.input(data);
+        digest.result()
+    }
+
+    pub fn hash_node<D>(&self, digest: &mut D, left: &[u8], right: Option<&[u8]>) -> Array
+    where
+        D: Digest,
+    {
+        match (self, right) {
+            (Mode::Plain, right) => {
+                digest.input(left);
+                if let Some(right) = right {
+                    digest.input(right);
+                }
+            }
+            (Mode::Rfc6962, Some(right)) => {
+                digest.input([Self::NODE_PREFIX]);
+                digest.input(left);
+                digest.input(right);
+            }
+            (Mode::Rfc6962, None) => return left.to_vec(),
+        }
+
+        digest.result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use digest::sha256::Sha256;
+
+    #[test]
+    fn test_domain_separation() {
+        let mut digest = Sha256::new();
+        let data = [1u8; 64];
+
+        let plain_leaf = Mode::Plain.hash_leaf(&mut digest, &data);
+        let plain_node = Mode::Plain.hash_node(&mut digest, &data[..32], Some(&data[32..]));
+        assert_eq!(plain_leaf, plain_node);
+
+        let leaf = Mode::Rfc6962.hash_leaf(&mut digest, &data);
+        let node = Mode::Rfc6962.hash_node(&mut digest, &data[..32], Some(&data[32..]));
+        assert_ne!(leaf, node);
+        assert_ne!(leaf, plain_leaf);
+    }
+
+    #[test]
+    fn test_lone_node() {
+        let mut digest = Sha256::new();
+        let hash = [2u8; 32];
+
+        assert_ne!(
+            Mode::Plain.hash_node(&mut digest, &hash, None)[..],
+            hash[..]
+        );
+        assert_eq!(
+            Mode::Rfc6962.hash_node(&mut digest, &hash, None)[..],
+            hash[..]
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+
+use super::error::ErrorKind;
+use super::Result;
+use digest::Digest;
+use mode::Mode;
+use Array;
+
+/// RFC 6962 proof that a tree of `old_size` leaves is a prefix of a tree
+/// of `new_size` leaves
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ConsistencyProof {
+    pub old_size: usize,
+    pub new_size: usize,
+    pub nodes: Vec<Array>,
+}
+
+impl ConsistencyProof {
+    /// Build a proof from subtree hashes. `get` returns the hash of the
+    /// subtree spanning the `[start, end)` leaves.
+    pub(crate) fn build<F>(old_size: usize, new_size: usize, get: F) -> Result<Self>
+    where
+        F: Fn(usize, usize) -> Option<Array>,
+    {
+        let mut nodes = Vec::new();
+        if old_size < new_size {
+            Self::subproof(old_size, 0, new_size, true, &get, &mut nodes)?;
+        }
+
+        Ok(ConsistencyProof {
+            old_size,
+            new_size,
+            nodes,
+        })
+    }
+
+    fn subproof<F>(
+        m: usize,
+        start: usize,
+        end: usize,
+        complete: bool,
+        get: &F,
+        nodes: &mut Vec<Array>,
+    ) -> Result<()>
+    where
+        F: Fn(usize, usize) -> Option<Array>,
+    {
+        let n = end - start;
+        if m == n {
+            if !complete {
+                nodes.push(Self::subtree(start, end, get)?);
+            }
+            return Ok(());
+        }
+
+        let k = n.next_power_of_two() >> 1;
+        if m <= k {
+            Self::subproof(m, start, start + k, complete, get, nodes)?;
+            nodes.push(Self::subtree(start + k, end, get)?);
+        } else {
+            Self::subproof(m - k, start + k, end, false, get, nodes)?;
+            nodes.push(Self::subtree(start, start + k, get)?);
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn subtree<F>(start: usize, end: usize, get: &F) -> Result<Array>
+    where
+        F: Fn(usize, usize) -> Option<Array>,
+    {
+        match get(start, end) {
+            Some(hash) => Ok(hash),
+            None => proof_err!(ErrorKind::PartialProof, (start, end)),
+        }
+    }
+
+    /// Verify the proof without a tree, given both roots
+    pub fn verify_against_roots<D>(&self, old_root: &[u8], new_root: &[u8]) -> Result<()>
+    where
+        D: Digest,
+    {
+        if self.old_size == 0 || self.old_size > self.new_size {
+            return proof_err!(ErrorKind::IndexOutOfRange, self.old_size);
+        }
+        if self.old_size == self.new_size {
+            if !self.nodes.is_empty() {
+                return proof_err!(ErrorKind::InvalidLength, self.nodes.len());
+            }
+            if old_root != new_root {
+                return proof_err!(ErrorKind::InvalidHash, "root hash mismatch");
+            }
+            return Ok(());
+        }
+
+        let mut nodes: Vec<&[u8]> = self.nodes.iter().map(|node| &node[..]).collect();
+        if self.old_size.is_power_of_two() {
+            nodes.insert(0, old_root);
+        }
+        if nodes.is_empty() {
+            return proof_err!(ErrorKind::InvalidLength, 0);
+        }
+
+        let mut digest = D::new();
+        let mut fn_ = self.old_size - 1;
+        let mut sn = self.new_size - 1;
+
+        while fn_ & 1 == 1 {
+            fn_ >>= 1;
+            sn >>= 1;
+        }
+
+        let mut fr = nodes[0].to_vec();
+        let mut sr = nodes[0].to_vec();
+
+        for node in nodes[1..].iter() {
+            if sn == 0 {
+                return proof_err!(ErrorKind::InvalidLength, self.nodes.len());
+            }
+
+            if fn_ & 1 == 1 || fn_ == sn {
+                fr = Mode::Rfc6962.hash_node(&mut digest, node, Some(&fr));
+                sr = Mode::Rfc6962.hash_node(&mut digest, node, Some(&sr));
+
+                while fn_ & 1 == 0 && fn_ != 0 {
+                    fn_ >>= 1;
+                    sn >>= 1;
+                }
+            } else {
+                sr = Mode::Rfc6962.hash_node(&mut digest, &sr, Some(node));
+            }
+
+            fn_ >>= 1;
+            sn >>= 1;
+        }
+
+        if sn != 0 {
+            return proof_err!(ErrorKind::InvalidLength, self.nodes.len());
+        }
+        if &fr[..] != old_root || &sr[..] != new_root {
+            return proof_err!(ErrorKind::InvalidHash, "root hash mismatch");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+//! Canonical binary encoding of a `Proof`, independent of serde:
+//!
+//! | size          | field                                          |
+//! |---------------|------------------------------------------------|
+//! | 1             | format version                                 |
+//! | 1             | digest algorithm id                            |
+//! | 1             | flags (bit 0: partial proof)                   |
+//! | 8             | leaf index, big endian                         |
+//! | 8             | tree leaf count, big endian                    |
+//! | 2             | path length, big endian                        |
+//! | ⌈length / 8⌉  | bitmask of present path entries, LSB first     |
+//! | n             | leaf hash                                      |
+//! | n * present   | present path entries                           |
+//!
+//! where `n` is the digest output size.
+
+use super::error::{Error, ErrorKind};
+use super::{path_shape, Proof, Result};
+use digest::{Algorithm, Digest};
+
+const VERSION: u8 = 1;
+const FLAG_PARTIAL: u8 = 1;
+const HEADER_SIZE: usize = 21;
+
+impl Proof {
+    pub fn to_bytes<D>(&self) -> Result<Vec<u8>>
+    where
+        D: Digest,
+    {
+        let size = D::output_size();
+        let path_len = self.path.len();
+
+        if path_len > u16::MAX as usize {
+            return proof_err!(ErrorKind::InvalidLength, path_len);
+        }
+        if self.leaf_hash.len() != size {
+            return proof_err!(ErrorKind::InvalidHash, self.leaf_index);
+        }
+        if let Some(Some(entry)) = self.path.iter().find(|e| e.iter().any(|h| h.len() != size)) {
+            return proof_err!(ErrorKind::InvalidHash, entry.len());
+        }
+
+        let mask_len = mask_len(path_len);
+        let present = self.path.iter().filter(|e| e.is_some()).count();
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + mask_len + size * (1 + present));
+
+        bytes.push(VERSION);
+        bytes.push(D::algorithm().id());
+        bytes.push(if self.partial { FLAG_PARTIAL } else { 0 });
+        bytes.extend_from_slice(&(self.leaf_index as u64).to_be_bytes());
+        bytes.extend_from_slice(&(self.leaf_count as u64).to_be_bytes());
+        bytes.extend_from_slice(&(path_len as u16).to_be_bytes());
+
+        let mut mask = vec![0u8; mask_len];
+        for (i, entry) in self.path.iter().enumerate() {
+            if entry.is_some() {
+                mask[i / 8] |= 1 << (i % 8);
+            }
+        }
+        bytes.extend_from_slice(&mask);
+        bytes.extend_from_slice(&self.leaf_hash);
+
+        for hash in self.path.iter().flatten() {
+            bytes.extend_from_slice(hash);
+        }
+
+        Ok(bytes)
+    }
+
+    /// Decode a proof, rejecting any input that is not in canonical form
+    /// or does not match the shape of the tree
+    pub fn from_bytes<D>(bytes: &[u8]) -> Result<Self>
+    where
+        D: Digest,
+    {
+        if bytes.len() < HEADER_SIZE {
+            return invalid("truncated header");
+        }
+        if bytes[0] != VERSION {
+            return invalid("unsupported version");
+        }
+        if Algorithm::from_id(bytes[1]) != Some(D::algorithm()) {
+            return invalid("digest algorithm mismatch");
+        }
+        if bytes[2] & !FLAG_PARTIAL != 0 {
+            return invalid("unknown flags");
+        }
+
+        let partial = bytes[2] & FLAG_PARTIAL != 0;
+        let leaf_index = read_u64(&bytes[3..11])?;
+        let leaf_count = read_u64(&bytes[11..19])?;
+        let path_len = u16::from_be_bytes([bytes[19], bytes[20]]) as usize;
+
+        if leaf_index >= leaf_count {
+            return proof_err!(ErrorKind::IndexOutOfRange, leaf_index);
+        }
+
+        let shape = match path_shape(leaf_index, leaf_count) {
+            Some(shape) => shape,
+            None => return invalid("leaf count out of range"),
+        };
+        if path_len > shape.len() || (!partial && path_len != shape.len()) {
+            return proof_err!(ErrorKind::InvalidLength, path_len);
+        }
+
+        let size = D::output_size();
+        let mask_len = mask_len(path_len);
+        let mask = match bytes.get(HEADER_SIZE..HEADER_SIZE + mask_len) {
+            Some(mask) => mask,
+            None => return invalid("truncated bitmask"),
+        };
+        if path_len & 7 != 0 && mask[mask_len - 1] >> (path_len & 7) != 0 {
+            return invalid("padding bits set");
+        }
+
+        let mut offset = HEADER_SIZE + mask_len;
+        let mut hashes = bytes[offset..].chunks(size);
+        let leaf_hash = match hashes.next() {
+            Some(hash) if hash.len() == size => hash.to_vec(),
+            _ => return invalid("truncated leaf hash"),
+        };
+        offset += size;
+
+        let mut path = Vec::with_capacity(path_len);
+
+        for (i, expected) in shape[..path_len].iter().enumerate() {
+            let present = mask[i / 8] & (1 << (i % 8)) != 0;
+            if present != *expected {
+                return invalid("path does not match the tree shape");
+            }
+
+            if present {
+                match hashes.next() {
+                    Some(hash) if hash.len() == size => path.push(Some(hash.to_vec())),
+                    _ => return invalid("truncated path"),
+                }
+                offset += size;
+            } else {
+                path.push(None);
+            }
+        }
+
+        if offset != bytes.len() {
+            return invalid("trailing bytes");
+        }
+
+        Ok(Proof {
+            leaf_index,
+            leaf_count,
+            leaf_hash,
+            path,
+            partial,
+        })
+    }
+}
+
+#[inline]
+fn mask_len(path_len: usize) -> usize {
+    (path_len + 7) >> 3
+}
+
+#[inline]
+fn invalid<T>(message: &str) -> Result<T> {
+    Err(Error::new(ErrorKind::InvalidEncoding, message))
+}
+
+#[inline]
+fn read_u64(bytes: &[u8]) -> Result<usize> {
+    let mut value = [0u8; 8];
+    value.copy_from_slice(bytes);
+
+    let value = u64::from_be_bytes(value);
+    if value > usize::MAX as u64 {
+        return invalid("value out of range");
+    }
+    Ok(value as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use digest::sha256::Sha256;
+    use digest::sha512::Sha512;
+    use mode::Mode;
+    use proof::Provable;
+    use streaming_iterator::convert;
+    use tree::MerkleTree;
+
+    fn tree<D: Digest>(leaf_count: usize) -> MerkleTree<D> {
+        let leaves: Vec<Vec<u8>> = (0..leaf_count).map(|i| vec![i as u8; 64]).collect();
+        MerkleTree::<D>::from_data(convert(leaves), Mode::Rfc6962)
+    }
+
+    fn decode_error(bytes: &[u8]) -> ErrorKind {
+        Proof::from_bytes::<Sha256>(bytes).unwrap_err().kind
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for leaf_count in [1, 2, 10, 13, 300].iter() {
+            let tree = tree::<Sha256>(*leaf_count);
+
+            for leaf in 0..*leaf_count {
+                let proof = tree.prove(leaf).unwrap();
+                let bytes = proof.to_bytes::<Sha256>().unwrap();
+                let present = proof.path.iter().filter(|e| e.is_some()).count();
+
+                assert_eq!(
+                    bytes.len(),
+                    HEADER_SIZE + mask_len(proof.path.len()) + 32 * (1 + present)
+                );
+
+                let decoded = Proof::from_bytes::<Sha256>(&bytes).unwrap();
+                assert_eq!(decoded.leaf_index, proof.leaf_index);
+                assert_eq!(decoded.leaf_count, proof.leaf_count);
+                assert_eq!(decoded.leaf_hash, proof.leaf_hash);
+                assert_eq!(decoded.path, proof.path);
+                assert_eq!(decoded.partial, proof.partial);
+                assert_eq!(decoded.to_bytes::<Sha256>().unwrap(), bytes);
+            }
+        }
+
+        let mut proof = tree::<Sha256>(10).prove(3).unwrap();
+        proof.partial = true;
+        proof.path.truncate(2);
+        let decoded = Proof::from_bytes::<Sha256>(&proof.to_bytes::<Sha256>().unwrap()).unwrap();
+        assert!(decoded.partial);
+        assert_eq!(decoded.path, proof.path);
+    }
+
+    #[test]
+    fn test_invalid_encoding() {
+        let proof = tree::<Sha256>(10).prove(4).unwrap();
+        let bytes = proof.to_bytes::<Sha256>().unwrap();
+
+        assert_eq!(decode_error(&bytes[..10]), ErrorKind::InvalidEncoding);
+        assert_eq!(
+            decode_error(&bytes[..bytes.len() - 1]),
+            ErrorKind::InvalidEncoding
+        );
+
+        let mut invalid = bytes.clone();
+        invalid.push(0);
+        assert_eq!(decode_error(&invalid), ErrorKind::InvalidEncoding);
+
+        let mut invalid = bytes.clone();
+        invalid[0] = 2;
+        assert_eq!(decode_error(&invalid), ErrorKind::InvalidEncoding);
+
+        let mut invalid = bytes.clone();
+        invalid[2] = 2;
+        assert_eq!(decode_error(&invalid), ErrorKind::InvalidEncoding);
+
+        // flip the bit of the root level entry, which is never present
+        let mut invalid = bytes.clone();
+        invalid[HEADER_SIZE] ^= 1 << (proof.path.len() - 1);
+        assert_eq!(decode_error(&invalid), ErrorKind::InvalidEncoding);
+
+        let mut invalid = bytes.clone();
+        invalid[18] = 4;
+        assert_eq!(decode_error(&invalid), ErrorKind::IndexOutOfRange);
+
+        let mut invalid = bytes.clone();
+        invalid[20] += 1;
+        assert_eq!(decode_error(&invalid), ErrorKind::InvalidLength);
+
+        // a leaf count of u64::MAX overflows the size of the tree
+        let mut invalid = bytes.clone();
+        invalid[11..19].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert_eq!(decode_error(&invalid), ErrorKind::InvalidEncoding);
+
+        assert_eq!(
+            Proof::from_bytes::<Sha512>(&bytes).unwrap_err().kind,
+            ErrorKind::InvalidEncoding
+        );
+        assert!(proof.to_bytes::<Sha512>().is_err());
+    }
+}
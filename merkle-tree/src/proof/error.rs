@@ -10,7 +10,9 @@ pub enum ErrorKind {
     InvalidLength,
     InvalidIndex,
     InvalidHash,
+    InvalidEncoding,
     PartialProof,
+    UnsupportedMode,
 }
 
 #[derive(Clone, Debug)]
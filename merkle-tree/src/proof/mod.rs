@@ -1,10 +1,21 @@
 #[macro_use]
 pub mod error;
+pub mod consistency;
+pub mod encoding;
+pub mod multi;
+pub mod range;
 
 use self::error::{Error, ErrorKind};
+use digest::Digest;
+use mode::Mode;
 use serde::{Deserialize, Serialize};
+use tree::checked_tree_size;
 use Array;
 
+pub use self::consistency::ConsistencyProof;
+pub use self::multi::{MultiProof, MultiProvable};
+pub use self::range::RangeProof;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 pub trait Provable<E> {
@@ -12,9 +23,19 @@ pub trait Provable<E> {
     fn verify(&self, proof: &Proof) -> std::result::Result<(), E>;
 }
 
+/// Leaf input of a stateless proof verification
+#[derive(Clone, Copy, Debug)]
+pub enum Leaf<'l> {
+    /// Raw leaf data, hashed by the verifier
+    Data(&'l [u8]),
+    /// Precomputed leaf hash
+    Hash(&'l [u8]),
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Proof {
     pub leaf_index: usize,
+    pub leaf_count: usize,
     pub leaf_hash: Array,
     pub path: Vec<Option<Array>>,
     pub partial: bool,
@@ -41,4 +62,84 @@ impl Proof {
         }
         Ok(())
     }
+
+    /// Verify the proof without a local tree, by walking the path from the
+    /// leaf up to the root. Hashes are computed according to `mode`.
+    pub fn verify_against_root<D>(&self, root: &[u8], leaf: Leaf, mode: Mode) -> Result<()>
+    where
+        D: Digest,
+    {
+        if self.partial {
+            return proof_err!(ErrorKind::PartialProof, "cannot verify a partial proof");
+        }
+        if self.leaf_index >= self.leaf_count {
+            return proof_err!(ErrorKind::IndexOutOfRange, self.leaf_index);
+        }
+
+        let shape = match path_shape(self.leaf_index, self.leaf_count) {
+            Some(shape) => shape,
+            None => return proof_err!(ErrorKind::InvalidLength, self.leaf_count),
+        };
+        if self.path.len() != shape.len() {
+            return proof_err!(ErrorKind::InvalidLength, self.path.len());
+        }
+        if self
+            .path
+            .iter()
+            .zip(shape.iter())
+            .any(|(entry, present)| entry.is_some() != *present)
+        {
+            return proof_err!(
+                ErrorKind::InvalidIndex,
+                "path does not match the tree shape"
+            );
+        }
+
+        let mut digest = D::new();
+        let leaf_hash = match leaf {
+            Leaf::Data(data) => mode.hash_leaf(&mut digest, data),
+            Leaf::Hash(hash) => hash.to_vec(),
+        };
+
+        if leaf_hash != self.leaf_hash {
+            return proof_err!(ErrorKind::InvalidHash, self.leaf_index);
+        }
+
+        let mut hash = leaf_hash;
+        let mut position = self.leaf_index;
+
+        // the last path entry belongs to the root level
+        for entry in self.path[..self.path.len() - 1].iter() {
+            hash = match entry {
+                Some(sibling) if position & 1 == 1 => {
+                    mode.hash_node(&mut digest, sibling, Some(&hash))
+                }
+                Some(sibling) => mode.hash_node(&mut digest, &hash, Some(sibling)),
+                None => mode.hash_node(&mut digest, &hash, None),
+            };
+            position >>= 1;
+        }
+
+        if &hash[..] != root {
+            return proof_err!(ErrorKind::InvalidHash, "root hash mismatch");
+        }
+        Ok(())
+    }
+}
+
+/// Whether each entry of a full proof path holds a sibling hash, from the
+/// leaf level up to the root. `None` if the tree size overflows.
+pub(crate) fn path_shape(leaf_index: usize, leaf_count: usize) -> Option<Vec<bool>> {
+    let (_, height) = checked_tree_size(leaf_count)?;
+    let mut shape = Vec::with_capacity(height);
+    let mut position = leaf_index;
+    let mut level_len = leaf_count;
+
+    for _ in 0..height {
+        shape.push(position & 1 == 1 || position + 1 < level_len);
+        position >>= 1;
+        level_len = (level_len >> 1) + (level_len & 1);
+    }
+
+    Some(shape)
 }
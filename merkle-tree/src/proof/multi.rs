@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+
+use super::error::{Error, ErrorKind};
+use super::{Leaf, Result};
+use digest::Digest;
+use mode::Mode;
+use tree::{checked_tree_size, tree_size};
+use Array;
+
+pub trait MultiProvable<E> {
+    fn prove_multi(&self, leaf_indices: &[usize]) -> std::result::Result<MultiProof, E>;
+    fn verify_multi(&self, proof: &MultiProof) -> std::result::Result<(), E>;
+}
+
+/// Proof of multiple leaves of a single tree. Only the nodes that cannot be
+/// computed from the proven leaves (or their ancestors) are included.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MultiProof {
+    pub leaf_count: usize,
+    /// sorted, unique indices of proven leaves
+    pub leaf_indices: Vec<usize>,
+    pub leaf_hashes: Vec<Array>,
+    /// missing sibling hashes, ordered by level and position within a level
+    pub nodes: Vec<Array>,
+}
+
+impl MultiProof {
+    /// Build a proof by walking the tree levels from the leaves up. `get` is
+    /// called with level-relative node positions and returns node hashes.
+    pub(crate) fn build<F>(leaf_count: usize, leaf_indices: &[usize], get: F) -> Result<Self>
+    where
+        F: Fn(usize, usize) -> Option<Array>,
+    {
+        let mut indices = leaf_indices.to_vec();
+        indices.sort();
+        indices.dedup();
+
+        if indices.is_empty() {
+            return proof_err!(ErrorKind::InvalidLength, 0);
+        }
+        if let Some(index) = indices.iter().find(|index| **index >= leaf_count) {
+            return proof_err!(ErrorKind::IndexOutOfRange, index);
+        }
+
+        let mut leaf_hashes = Vec::with_capacity(indices.len());
+        for index in indices.iter() {
+            match get(0, *index) {
+                Some(hash) => leaf_hashes.push(hash),
+                None => return proof_err!(ErrorKind::PartialProof, index),
+            }
+        }
+
+        let mut nodes = Vec::new();
+        let mut known = indices.clone();
+        let mut level_len = leaf_count;
+        let (_, height) = tree_size(leaf_count);
+
+        for level in 0..height - 1 {
+            for (i, position) in known.iter().enumerate() {
+                let sibling = position ^ 1;
+                let is_known = if position & 1 == 1 {
+                    i > 0 && known[i - 1] == sibling
+                } else {
+                    known.get(i + 1) == Some(&sibling)
+                };
+
+                if is_known || sibling >= level_len {
+                    continue;
+                }
+                match get(level, sibling) {
+                    Some(hash) => nodes.push(hash),
+                    None => return proof_err!(ErrorKind::PartialProof, (level, sibling)),
+                }
+            }
+
+            known = known.iter().map(|position| position >> 1).collect();
+            known.dedup();
+            level_len = (level_len + 1) >> 1;
+        }
+
+        Ok(MultiProof {
+            leaf_count,
+            leaf_indices: indices,
+            leaf_hashes,
+            nodes,
+        })
+    }
+
+    /// Verify the proof without a local tree. `leaves` correspond to
+    /// `leaf_indices` and are hashed according to `mode`.
+    pub fn verify_against_root<D>(&self, root: &[u8], leaves: &[Leaf], mode: Mode) -> Result<()>
+    where
+        D: Digest,
+    {
+        if self.leaf_indices.is_empty()
+            || self.leaf_indices.len() != self.leaf_hashes.len()
+            || self.leaf_indices.len() != leaves.len()
+        {
+            return proof_err!(ErrorKind::InvalidLength, leaves.len());
+        }
+        if self.leaf_indices.windows(2).any(|w| w[0] >= w[1]) {
+            return proof_err!(ErrorKind::InvalidIndex, "leaf indices not sorted");
+        }
+        if let Some(index) = self.leaf_indices.iter().find(|i| **i >= self.leaf_count) {
+            return proof_err!(ErrorKind::IndexOutOfRange, index);
+        }
+
+        let mut digest = D::new();
+        let mut known = Vec::with_capacity(leaves.len());
+
+        for (i, leaf) in leaves.iter().enumerate() {
+            let hash = match leaf {
+                Leaf::Data(data) => mode.hash_leaf(&mut digest, data),
+                Leaf::Hash(hash) => hash.to_vec(),
+            };
+            if hash != self.leaf_hashes[i] {
+                return proof_err!(ErrorKind::InvalidHash, self.leaf_indices[i]);
+            }
+            known.push((self.leaf_indices[i], hash));
+        }
+
+        let (_, height) = match checked_tree_size(self.leaf_count) {
+            Some(size) => size,
+            None => return proof_err!(ErrorKind::InvalidLength, self.leaf_count),
+        };
+        let mut nodes = self.nodes.iter();
+        let mut level_len = self.leaf_count;
+
+        for _ in 0..height - 1 {
+            let mut parents: Vec<(usize, Array)> = Vec::with_capacity(known.len());
+            let mut i = 0;
+
+            while i < known.len() {
+                let (position, ref hash) = known[i];
+                let next = known.get(i + 1).map(|(p, h)| (*p, h));
+
+                let parent = match next {
+                    Some((p, right)) if position & 1 == 0 && p == position + 1 => {
+                        i += 1;
+                        mode.hash_node(&mut digest, hash, Some(right))
+                    }
+                    _ if position & 1 == 1 => {
+                        let left = Self::next_node(&mut nodes)?;
+                        mode.hash_node(&mut digest, left, Some(hash))
+                    }
+                    _ if position + 1 < level_len => {
+                        let right = Self::next_node(&mut nodes)?;
+                        mode.hash_node(&mut digest, hash, Some(right))
+                    }
+                    _ => mode.hash_node(&mut digest, hash, None),
+                };
+
+                parents.push((position >> 1, parent));
+                i += 1;
+            }
+
+            known = parents;
+            level_len = (level_len >> 1) + (level_len & 1);
+        }
+
+        if nodes.next().is_some() {
+            return proof_err!(ErrorKind::InvalidLength, self.nodes.len());
+        }
+        if known.len() != 1 || &known[0].1[..] != root {
+            return proof_err!(ErrorKind::InvalidHash, "root hash mismatch");
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn next_node<'n, I>(nodes: &mut I) -> Result<&'n Array>
+    where
+        I: Iterator<Item = &'n Array>,
+    {
+        match nodes.next() {
+            Some(node) => Ok(node),
+            None => Err(Error::new(ErrorKind::InvalidLength, "missing proof node")),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use super::error::ErrorKind;
+use super::{Leaf, MultiProof, Result};
+use digest::Digest;
+use mode::Mode;
+use Array;
+
+/// Proof of a contiguous range of leaves. Only the nodes on the paths of
+/// the range boundaries are included; leaf hashes are computed by the
+/// verifier.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RangeProof {
+    pub leaf_count: usize,
+    pub start: usize,
+    pub end: usize,
+    pub nodes: Vec<Array>,
+}
+
+impl RangeProof {
+    pub(crate) fn from_multi(range: Range<usize>, proof: MultiProof) -> Self {
+        RangeProof {
+            leaf_count: proof.leaf_count,
+            start: range.start,
+            end: range.end,
+            nodes: proof.nodes,
+        }
+    }
+
+    #[inline]
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// Verify the proof without a local tree. `leaves` hold every leaf of
+    /// the range, in order.
+    pub fn verify_against_root<D>(&self, root: &[u8], leaves: &[Leaf], mode: Mode) -> Result<()>
+    where
+        D: Digest,
+    {
+        if self.start >= self.end || leaves.len() != self.end - self.start {
+            return proof_err!(ErrorKind::InvalidLength, leaves.len());
+        }
+
+        let mut digest = D::new();
+        let leaf_hashes: Vec<Array> = leaves
+            .iter()
+            .map(|leaf| match leaf {
+                Leaf::Data(data) => mode.hash_leaf(&mut digest, data),
+                Leaf::Hash(hash) => hash.to_vec(),
+            })
+            .collect();
+
+        let proof = MultiProof {
+            leaf_count: self.leaf_count,
+            leaf_indices: self.range().collect(),
+            leaf_hashes,
+            nodes: self.nodes.clone(),
+        };
+        let hashes: Vec<Leaf> = proof
+            .leaf_hashes
+            .iter()
+            .map(|hash| Leaf::Hash(hash))
+            .collect();
+        proof.verify_against_root::<D>(root, &hashes, mode)
+    }
+}
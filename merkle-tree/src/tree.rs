@@ -1,19 +1,23 @@
 use std::marker::PhantomData;
+use std::ops::Range;
 
 use bit_vec::BitVec;
 use bit_vec_serde::BitVecSerde;
 use serde::{Deserialize, Serialize};
 use streaming_iterator::StreamingIterator;
 
+use diff::{NodeRequest, TreeDiff};
 use digest::Digest;
-use level::IndexedLevel;
+use level::{IndexedLevel, Level};
+use mode::Mode;
 use proof;
 use proof::error::{Error, ErrorKind};
-use proof::{Proof, Provable};
+use proof::{ConsistencyProof, MultiProof, MultiProvable, Proof, Provable, RangeProof};
 
 use {Array, Result};
 
 #[derive(Serialize, Deserialize)]
+#[serde(from = "SavedMerkleTree<D>")]
 pub struct MerkleTree<D>
 where
     D: Digest,
@@ -28,15 +32,89 @@ where
     height: usize,
     /// tree leaf count
     leaf_count: usize,
+    /// leaf and node hashing mode
+    mode: Mode,
     /// type holder
     phantom: PhantomData<D>,
 }
 
+/// Serialized form of a `MerkleTree`
+#[derive(Deserialize)]
+struct SavedMerkleTree<D>
+where
+    D: Digest,
+{
+    #[serde(with = "BitVecSerde")]
+    bitmap: BitVec,
+    #[serde(with = "serde_bytes")]
+    hashes: Vec<u8>,
+    height: usize,
+    leaf_count: usize,
+    mode: Mode,
+    phantom: PhantomData<D>,
+}
+
+impl<D> From<SavedMerkleTree<D>> for MerkleTree<D>
+where
+    D: Digest,
+{
+    fn from(tree: SavedMerkleTree<D>) -> Self {
+        // Serialized bitmaps are padded to whole bytes
+        let mut bitmap = tree.bitmap;
+        bitmap.truncate(tree.hashes.len() / D::output_size());
+
+        MerkleTree {
+            bitmap,
+            hashes: tree.hashes,
+            height: tree.height,
+            leaf_count: tree.leaf_count,
+            mode: tree.mode,
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Serialized form of trees saved before their hashing mode was recorded,
+/// which always hashed in `Mode::Plain`
+#[derive(Deserialize)]
+pub struct LegacyMerkleTree<D>
+where
+    D: Digest,
+{
+    #[serde(with = "BitVecSerde")]
+    bitmap: BitVec,
+    #[serde(with = "serde_bytes")]
+    hashes: Vec<u8>,
+    height: usize,
+    leaf_count: usize,
+    phantom: PhantomData<D>,
+}
+
+impl<D> From<LegacyMerkleTree<D>> for MerkleTree<D>
+where
+    D: Digest,
+{
+    fn from(tree: LegacyMerkleTree<D>) -> Self {
+        // Serialized bitmaps are padded to whole bytes
+        let mut bitmap = tree.bitmap;
+        bitmap.truncate(tree.hashes.len() / D::output_size());
+
+        MerkleTree {
+            bitmap,
+            hashes: tree.hashes,
+            height: tree.height,
+            leaf_count: tree.leaf_count,
+            mode: Mode::Plain,
+            phantom: PhantomData,
+        }
+    }
+}
+
 impl<D> MerkleTree<D>
 where
     D: Digest,
 {
-    fn new(leaf_count: usize) -> Self {
+    fn new(leaf_count: usize, mode: Mode) -> Self {
         let (size, height) = tree_size(leaf_count);
         let hashes = vec![0 as u8; size * D::output_size()];
         let bitmap = BitVec::from_elem(size, false);
@@ -46,10 +124,134 @@ where
             hashes,
             height,
             leaf_count,
+            mode,
             phantom: PhantomData,
         }
     }
 
+    /// Build a tree by hashing leaf data in the given mode
+    pub fn from_data<I>(input: I, mode: Mode) -> Self
+    where
+        I: StreamingIterator<Item = Array>,
+    {
+        let mut hashes = build_leaves::<I, D>(input, mode);
+        let leaf_count = hashes.len() / D::output_size();
+
+        let (size, height) = tree_size(leaf_count);
+        hashes.resize(size * D::output_size(), 0 as u8);
+
+        let mut bitmap = BitVec::from_elem(leaf_count, true);
+        bitmap.grow(size - leaf_count, false);
+
+        let mut tree = MerkleTree {
+            bitmap,
+            hashes,
+            height,
+            leaf_count,
+            mode,
+            phantom: PhantomData,
+        };
+
+        tree.build();
+        tree
+    }
+
+    /// Build a complete tree from precomputed leaf hashes
+    pub fn from_hashes(leaves: &[Array], mode: Mode) -> Result<Self> {
+        let mut tree = Self::new(leaves.len(), mode);
+
+        for (index, hash) in leaves.iter().enumerate() {
+            if hash.len() != D::output_size() {
+                return err!("Invalid hash length {:?} at leaf {:?}", hash.len(), index);
+            }
+            tree.set_hash(index, hash);
+        }
+
+        tree.build();
+        Ok(tree)
+    }
+
+    /// Create a partial tree holding only the root hash. Remaining nodes are
+    /// filled in with `insert_proof`.
+    pub fn from_root(leaf_count: usize, root: &Array, mode: Mode) -> Result<Self> {
+        if leaf_count == 0 {
+            return err!("Invalid leaf count {:?}", leaf_count);
+        }
+        if root.len() != D::output_size() {
+            return err!("Invalid root hash length {:?}", root.len());
+        }
+
+        let mut tree = Self::new(leaf_count, mode);
+        let index = tree.bitmap.len() - 1;
+        tree.set_hash(index, root);
+        Ok(tree)
+    }
+
+    /// Verify a proof against the tree root and store the leaf hash along
+    /// with all nodes on its path
+    pub fn insert_proof(&mut self, proof: &Proof) -> proof::Result<()> {
+        if proof.leaf_index >= self.leaf_count {
+            return proof_err!(ErrorKind::IndexOutOfRange, proof.leaf_index);
+        }
+        if proof.path.len() != self.height {
+            return proof_err!(ErrorKind::InvalidLength, proof.path.len());
+        }
+
+        let root = match self.root() {
+            Some(root) => root,
+            None => return proof_err!(ErrorKind::PartialProof, "tree root is not set"),
+        };
+        let leaf = proof::Leaf::Hash(&proof.leaf_hash);
+        proof.verify_against_root::<D>(&root, leaf, self.mode)?;
+
+        let mut digest = D::new();
+        let mut hash = proof.leaf_hash.clone();
+        let mut ilevel = IndexedLevel::new(proof.leaf_index, 0, self.leaf_count).unwrap();
+
+        self.set_hash(proof.leaf_index, &hash);
+
+        for entry in proof.path[..self.height - 1].iter() {
+            hash = match (ilevel.sibling(), entry) {
+                (Some(index), Some(sibling)) => {
+                    self.set_hash(index, sibling);
+                    if index < ilevel.index {
+                        self.mode.hash_node(&mut digest, sibling, Some(&hash))
+                    } else {
+                        self.mode.hash_node(&mut digest, &hash, Some(sibling))
+                    }
+                }
+                (None, None) => self.mode.hash_node(&mut digest, &hash, None),
+                _ => return proof_err!(ErrorKind::InvalidHash, proof.leaf_index),
+            };
+
+            self.set_hash(ilevel.parent(), &hash);
+            ilevel = ilevel.down().unwrap();
+        }
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Hash leaf data according to the tree mode
+    pub fn hash_leaf(&self, data: &[u8]) -> Array {
+        self.mode.hash_leaf(&mut D::new(), data)
+    }
+
+    #[inline(always)]
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    pub fn leaves(&self) -> Vec<Array> {
+        (0..self.leaf_count)
+            .map(|index| self.get_hash(index).to_vec())
+            .collect()
+    }
+
     #[inline(always)]
     pub fn has(&self, index: usize) -> bool {
         match self.bitmap.get(index) {
@@ -78,6 +280,113 @@ where
         Ok(())
     }
 
+    pub fn root(&self) -> Option<Array> {
+        let index = self.bitmap.len() - 1;
+        if self.has(index) {
+            Some(self.get_hash(index).to_vec())
+        } else {
+            None
+        }
+    }
+
+    /// Prove a contiguous range of leaves
+    pub fn prove_range(&self, range: Range<usize>) -> proof::Result<RangeProof> {
+        if range.start >= range.end {
+            return proof_err!(ErrorKind::InvalidLength, range);
+        }
+        if range.end > self.leaf_count {
+            return proof_err!(ErrorKind::IndexOutOfRange, range);
+        }
+
+        let indices: Vec<usize> = range.clone().collect();
+        let proof = self.prove_multi(&indices)?;
+        Ok(RangeProof::from_multi(range, proof))
+    }
+
+    /// Append a leaf hash, growing the tree by a single leaf. Nodes of
+    /// complete subtrees are retained; the path of the new leaf is rebuilt.
+    pub fn push(&mut self, leaf_hash: &Array) -> Result<()> {
+        if leaf_hash.len() != D::output_size() {
+            return err!("Invalid hash length {:?}", leaf_hash.len());
+        }
+
+        let mut tree = Self::new(self.leaf_count + 1, self.mode);
+        let old_levels = levels(self.leaf_count);
+        let new_levels = levels(tree.leaf_count);
+
+        for (height, old) in old_levels.iter().enumerate() {
+            // nodes covering the last leaf may have been hashed alone
+            let complete = self.leaf_count >> height;
+            for position in 0..complete {
+                if self.has(old.start + position) {
+                    let hash = self.get_hash(old.start + position).to_vec();
+                    tree.set_hash(new_levels[height].start + position, &hash);
+                }
+            }
+        }
+
+        let leaf_index = self.leaf_count;
+        tree.set_hash(leaf_index, leaf_hash);
+        tree.build_down(leaf_index);
+
+        *self = tree;
+        Ok(())
+    }
+
+    /// Prove that the tree of the first `old_size` leaves is a prefix of
+    /// this tree. Only supported in `Mode::Rfc6962`.
+    pub fn prove_consistency(&self, old_size: usize) -> proof::Result<ConsistencyProof> {
+        if self.mode != Mode::Rfc6962 {
+            return proof_err!(ErrorKind::UnsupportedMode, self.mode);
+        }
+        if old_size == 0 || old_size > self.leaf_count {
+            return proof_err!(ErrorKind::IndexOutOfRange, old_size);
+        }
+
+        let levels = levels(self.leaf_count);
+        ConsistencyProof::build(old_size, self.leaf_count, |start, end| {
+            // subtrees are aligned to the smallest power of two covering them
+            let height = (end - start).next_power_of_two().trailing_zeros() as usize;
+            self.get_node(&levels[height], start >> height)
+        })
+    }
+
+    /// Hashes of the requested nodes; `None` for nodes that are not set
+    pub fn node_hashes(&self, request: &NodeRequest) -> Result<Vec<Option<Array>>> {
+        if request.leaf_count != self.leaf_count {
+            return err!("Leaf count mismatch: {:?}", request.leaf_count);
+        }
+
+        let levels = levels(self.leaf_count);
+        let mut hashes = Vec::with_capacity(request.nodes.len());
+
+        for node in request.nodes.iter() {
+            match levels.get(node.height) {
+                Some(level) => hashes.push(self.get_node(level, node.position)),
+                None => return err!("Node height {:?} out of range", node.height),
+            }
+        }
+
+        Ok(hashes)
+    }
+
+    /// Indices of leaves that differ from the other tree
+    pub fn diff(&self, other: &MerkleTree<D>) -> Result<Vec<usize>> {
+        if self.leaf_count != other.leaf_count || self.mode != other.mode {
+            return err!("Trees differ in shape or mode");
+        }
+
+        let mut diff = TreeDiff::new(self.leaf_count);
+        while !diff.is_done() {
+            let request = diff.request();
+            let local = self.node_hashes(&request)?;
+            let remote = other.node_hashes(&request)?;
+            diff.update(&local, &remote)?;
+        }
+
+        Ok(diff.leaves().to_vec())
+    }
+
     #[inline(always)]
     pub fn built(&self) -> bool {
         self.bitmap.all()
@@ -89,6 +398,16 @@ where
         &self.hashes[byte_index..byte_index + D::output_size()]
     }
 
+    #[inline]
+    fn get_node(&self, level: &Level, position: usize) -> Option<Array> {
+        let index = level.start + position;
+        if level.contains(index) && self.has(index) {
+            Some(self.get_hash(index).to_vec())
+        } else {
+            None
+        }
+    }
+
     fn set_hash(&mut self, index: usize, hash: &Array) {
         // update hash for node at index
         let byte_index = index * D::output_size();
@@ -111,16 +430,17 @@ where
         let mut ilevel = IndexedLevel::new(leaf_index, 0, self.leaf_count).unwrap();
 
         for _ in 0..self.height - 1 {
-            for sibling in ilevel.siblings().iter() {
-                if let Some(index) = sibling {
-                    if !self.has(*index) {
-                        return;
-                    }
-                    digest.input(&self.get_hash(*index));
-                }
+            let [left, right] = ilevel.siblings();
+            let left = left.unwrap();
+
+            if !self.has(left) || right.iter().any(|index| !self.has(*index)) {
+                return;
             }
 
-            self.set_hash(ilevel.parent(), &digest.result());
+            let right = right.map(|index| self.get_hash(index));
+            let hash = self.mode.hash_node(&mut digest, self.get_hash(left), right);
+
+            self.set_hash(ilevel.parent(), &hash);
             ilevel = ilevel.down().unwrap();
         }
     }
@@ -132,25 +452,7 @@ where
     I: StreamingIterator<Item = Array>,
 {
     fn from(input: I) -> Self {
-        let mut hashes = build_leaves::<I, D>(input);
-        let leaf_count = hashes.len() / D::output_size();
-
-        let (size, height) = tree_size(leaf_count);
-        hashes.resize(size * D::output_size(), 0 as u8);
-
-        let mut bitmap = BitVec::from_elem(leaf_count, true);
-        bitmap.grow(size - leaf_count, false);
-
-        let mut tree = MerkleTree {
-            bitmap,
-            hashes,
-            height,
-            leaf_count,
-            phantom: PhantomData,
-        };
-
-        tree.build();
-        tree
+        Self::from_data(input, Mode::default())
     }
 }
 
@@ -159,6 +461,13 @@ where
     D: Digest,
 {
     fn prove(&self, leaf_index: usize) -> proof::Result<Proof> {
+        if leaf_index >= self.leaf_count {
+            return proof_err!(ErrorKind::IndexOutOfRange, leaf_index);
+        }
+        if !self.has(leaf_index) {
+            return proof_err!(ErrorKind::PartialProof, leaf_index);
+        }
+
         let mut path = Vec::with_capacity(self.height);
         let mut ilevel = IndexedLevel::new(leaf_index, 0, self.leaf_count).unwrap();
 
@@ -184,9 +493,10 @@ where
 
         Ok(Proof {
             leaf_index,
+            leaf_count: self.leaf_count,
             leaf_hash: self.get_hash(leaf_index).to_vec(),
+            partial: path.len() < self.height,
             path,
-            partial: !self.built(),
         })
     }
 
@@ -197,6 +507,16 @@ where
         if proof.path.len() < 2 {
             return proof_err!(ErrorKind::InvalidLength, proof.path.len());
         }
+        if !self.has(proof.leaf_index) {
+            // the local path is unknown; fall back to the root hash
+            return match self.root() {
+                Some(root) => {
+                    let leaf = proof::Leaf::Hash(&proof.leaf_hash);
+                    proof.verify_against_root::<D>(&root, leaf, self.mode)
+                }
+                None => proof_err!(ErrorKind::PartialProof, proof.leaf_index),
+            };
+        }
         if self.get_hash(proof.leaf_index) != &proof.leaf_hash[..] {
             return proof_err!(ErrorKind::InvalidHash, proof.leaf_index);
         }
@@ -205,7 +525,37 @@ where
     }
 }
 
-fn build_leaves<I, D>(mut iter: I) -> Vec<u8>
+impl<D> MultiProvable<Error> for MerkleTree<D>
+where
+    D: Digest,
+{
+    fn prove_multi(&self, leaf_indices: &[usize]) -> proof::Result<MultiProof> {
+        let levels = levels(self.leaf_count);
+
+        MultiProof::build(self.leaf_count, leaf_indices, |level, position| {
+            self.get_node(&levels[level], position)
+        })
+    }
+
+    fn verify_multi(&self, proof: &MultiProof) -> proof::Result<()> {
+        if proof.leaf_count != self.leaf_count {
+            return proof_err!(ErrorKind::InvalidLength, proof.leaf_count);
+        }
+        let root = match self.root() {
+            Some(root) => root,
+            None => return proof_err!(ErrorKind::PartialProof, "tree root is not set"),
+        };
+
+        let leaves: Vec<proof::Leaf> = proof
+            .leaf_hashes
+            .iter()
+            .map(|hash| proof::Leaf::Hash(hash))
+            .collect();
+        proof.verify_against_root::<D>(&root, &leaves, self.mode)
+    }
+}
+
+fn build_leaves<I, D>(mut iter: I, mode: Mode) -> Vec<u8>
 where
     D: Digest,
     I: StreamingIterator<Item = Array>,
@@ -217,33 +567,52 @@ where
     };
 
     while let Some(data) = iter.next() {
-        digest.input(data);
-        leaves.extend_from_slice(&digest.result()[..]);
+        leaves.extend_from_slice(&mode.hash_leaf(&mut digest, data)[..]);
     }
 
     leaves
 }
 
-fn tree_size(mut leaf_count: usize) -> (usize, usize) {
+pub(crate) fn levels(leaf_count: usize) -> Vec<Level> {
+    let (_, height) = tree_size(leaf_count);
+    let mut levels = vec![Level::new(0, leaf_count)];
+
+    for _ in 1..height {
+        match levels.last().unwrap().down() {
+            Some(level) => levels.push(level),
+            None => break,
+        }
+    }
+
+    levels
+}
+
+pub(crate) fn tree_size(leaf_count: usize) -> (usize, usize) {
+    checked_tree_size(leaf_count).expect("tree size overflow")
+}
+
+/// Node count and height of a tree, or `None` if the node count overflows,
+/// e.g. for a leaf count read from an untrusted proof
+pub(crate) fn checked_tree_size(mut leaf_count: usize) -> Option<(usize, usize)> {
     let mut height = 0;
-    let mut sum = 0;
+    let mut sum: usize = 0;
 
     loop {
         height += 1;
-        sum += leaf_count;
+        sum = sum.checked_add(leaf_count)?;
         if leaf_count <= 1 {
             break;
         }
 
-        leaf_count = (leaf_count + 1) >> 1;
+        leaf_count = (leaf_count >> 1) + (leaf_count & 1);
     }
 
     if height == 1 {
-        sum += 1;
+        sum = sum.checked_add(1)?;
         height += 1;
     }
 
-    (sum, height)
+    Some((sum, height))
 }
 
 #[cfg(test)]
@@ -252,8 +621,9 @@ mod tests {
 
     use digest::sha512::Sha512;
     use level::Level;
-    use proof::Provable;
+    use proof::{Leaf, MultiProvable, Provable};
     use rand::Rng;
+    use streaming_iterator::convert;
 
     type D = Sha512;
 
@@ -282,6 +652,19 @@ mod tests {
         result
     }
 
+    /// Reference RFC 6962 tree hash, splitting at the largest power of two
+    fn rfc6962_root(leaves: &[Array]) -> Array {
+        let mut digest = D::new();
+        if leaves.len() == 1 {
+            return Mode::Rfc6962.hash_leaf(&mut digest, &leaves[0]);
+        }
+
+        let split = leaves.len().next_power_of_two() / 2;
+        let left = rfc6962_root(&leaves[..split]);
+        let right = rfc6962_root(&leaves[split..]);
+        Mode::Rfc6962.hash_node(&mut digest, &left, Some(&right))
+    }
+
     fn digests_to_bytes(source: &Vec<Array>) -> Array {
         let mut bytes = Array::new();
         source.iter().for_each(|l| {
@@ -295,19 +678,19 @@ mod tests {
     fn test_new() {
         let mut tree;
 
-        tree = MerkleTree::<D>::new(1);
+        tree = MerkleTree::<D>::new(1, Mode::Plain);
         assert_eq!(tree.leaf_count, 1);
         assert_eq!(tree.height, 2);
 
-        tree = MerkleTree::<D>::new(2);
+        tree = MerkleTree::<D>::new(2, Mode::Plain);
         assert_eq!(tree.leaf_count, 2);
         assert_eq!(tree.height, 2);
 
-        tree = MerkleTree::<D>::new(3);
+        tree = MerkleTree::<D>::new(3, Mode::Plain);
         assert_eq!(tree.leaf_count, 3);
         assert_eq!(tree.height, 3);
 
-        tree = MerkleTree::<D>::new(3);
+        tree = MerkleTree::<D>::new(3, Mode::Plain);
         assert_eq!(tree.leaf_count, 3);
         assert_eq!(tree.height, 3);
     }
@@ -327,7 +710,7 @@ mod tests {
             .collect();
 
         let mut level = Level::new(0, leaves.len());
-        let tree = MerkleTree::<D>::from(leaves.iter());
+        let tree = MerkleTree::<D>::from(convert(leaves.clone()));
         assert_eq!(tree.built(), true);
         assert_eq!(tree.leaf_count, leaf_count);
         assert_eq!(tree.height, 5);
@@ -350,13 +733,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_build_rfc6962() {
+        for leaf_count in [1 as usize, 2, 3, 5, 10, 13, 16].iter() {
+            let leaves = random_leaves(*leaf_count);
+            let tree = MerkleTree::<D>::from_data(convert(leaves.clone()), Mode::Rfc6962);
+            let plain = MerkleTree::<D>::from(convert(leaves.clone()));
+
+            assert_eq!(tree.built(), true);
+            assert_eq!(tree.mode(), Mode::Rfc6962);
+            assert_eq!(tree.height, plain.height);
+            assert_eq!(tree.root().unwrap(), rfc6962_root(&leaves));
+            assert_ne!(tree.root(), plain.root());
+            assert_eq!(tree.get(0).unwrap(), tree.hash_leaf(&leaves[0]));
+        }
+    }
+
+    #[test]
+    fn test_from_hashes() {
+        let source = MerkleTree::<D>::from(convert(random_leaves(13)));
+        let tree = MerkleTree::<D>::from_hashes(&source.leaves(), Mode::Plain).unwrap();
+
+        assert_eq!(tree.built(), true);
+        assert_eq!(tree.leaf_count, source.leaf_count);
+        assert_eq!(tree.height, source.height);
+        assert_eq!(tree.hashes[..], source.hashes[..]);
+
+        let invalid = vec![vec![0; D::output_size() - 1]];
+        assert!(MerkleTree::<D>::from_hashes(&invalid, Mode::Plain).is_err());
+    }
+
     #[test]
     fn test_create_proof() {
         let mut tree;
         let mut proof;
 
         for leaf_count in [1 as usize, 10, 13].iter() {
-            tree = MerkleTree::<D>::from(random_leaves(*leaf_count).iter());
+            tree = MerkleTree::<D>::from(convert(random_leaves(*leaf_count)));
             proof = tree.prove(leaf_count - 1).unwrap();
 
             assert_eq!(proof.leaf_index, leaf_count - 1);
@@ -368,7 +781,7 @@ mod tests {
     #[test]
     fn test_verify_proof() {
         for leaf_count in [1 as usize, 10, 13].iter() {
-            let tree = MerkleTree::<D>::from(random_leaves(*leaf_count).iter());
+            let tree = MerkleTree::<D>::from(convert(random_leaves(*leaf_count)));
             for leaf in 0..*leaf_count {
                 let proof = tree.prove(leaf).unwrap();
                 tree.verify(&proof).unwrap();
@@ -376,10 +789,451 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_root() {
+        let leaves = random_leaves(10);
+        let tree = MerkleTree::<D>::from(convert(leaves.clone()));
+        let root = tree.root().unwrap();
+
+        assert_eq!(root.len(), D::output_size());
+        assert_eq!(root[..], tree.get_hash(tree.bitmap.len() - 1)[..]);
+        assert_eq!(MerkleTree::<D>::new(10, Mode::Plain).root(), None);
+    }
+
+    #[test]
+    fn test_verify_against_root() {
+        for leaf_count in [1, 2, 10, 13].iter() {
+            let leaves = random_leaves(*leaf_count);
+            let tree = MerkleTree::<D>::from(convert(leaves.clone()));
+            let root = tree.root().unwrap();
+
+            for (leaf, data) in leaves.iter().enumerate() {
+                let proof = tree.prove(leaf).unwrap();
+                let hash = tree.get(leaf).unwrap();
+
+                proof
+                    .verify_against_root::<D>(&root, Leaf::Data(data), Mode::Plain)
+                    .unwrap();
+                proof
+                    .verify_against_root::<D>(&root, Leaf::Hash(&hash), Mode::Plain)
+                    .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_against_root_rfc6962() {
+        for leaf_count in [1, 2, 10, 13].iter() {
+            let leaves = random_leaves(*leaf_count);
+            let tree = MerkleTree::<D>::from_data(convert(leaves.clone()), Mode::Rfc6962);
+            let root = tree.root().unwrap();
+
+            for (leaf, data) in leaves.iter().enumerate() {
+                let proof = tree.prove(leaf).unwrap();
+                tree.verify(&proof).unwrap();
+
+                proof
+                    .verify_against_root::<D>(&root, Leaf::Data(data), Mode::Rfc6962)
+                    .unwrap();
+                assert!(proof
+                    .verify_against_root::<D>(&root, Leaf::Data(data), Mode::Plain)
+                    .is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_against_root_errors() {
+        let leaves = random_leaves(10);
+        let tree = MerkleTree::<D>::from(convert(leaves.clone()));
+        let root = tree.root().unwrap();
+        let verify = |proof: &Proof, root: &[u8], kind: ErrorKind| match proof
+            .verify_against_root::<D>(root, Leaf::Data(&leaves[3]), Mode::Plain)
+        {
+            Ok(()) => panic!("Proof verification should return an error"),
+            Err(err) => assert_eq!(err.kind, kind),
+        };
+
+        let mut proof = tree.prove(3).unwrap();
+        verify(&proof, &tree.get(0).unwrap(), ErrorKind::InvalidHash);
+
+        proof.path[1] = Some(tree.get(0).unwrap());
+        verify(&proof, &root, ErrorKind::InvalidHash);
+
+        proof.partial = true;
+        verify(&proof, &root, ErrorKind::PartialProof);
+
+        let mut proof = tree.prove(3).unwrap();
+        proof.leaf_index = 2;
+        verify(&proof, &root, ErrorKind::InvalidHash);
+
+        proof.leaf_index = 10;
+        verify(&proof, &root, ErrorKind::IndexOutOfRange);
+
+        let mut proof = tree.prove(3).unwrap();
+        proof.path.pop();
+        verify(&proof, &root, ErrorKind::InvalidLength);
+
+        let mut proof = tree.prove(3).unwrap();
+        proof.leaf_count = usize::MAX;
+        verify(&proof, &root, ErrorKind::InvalidLength);
+    }
+
+    #[test]
+    fn test_verify_against_root_shape() {
+        let leaves = random_leaves(5);
+        let tree = MerkleTree::<D>::from_data(convert(leaves.clone()), Mode::Rfc6962);
+        let root = tree.root().unwrap();
+        let verify = |proof: &Proof| {
+            proof.verify_against_root::<D>(&root, Leaf::Data(&leaves[4]), Mode::Rfc6962)
+        };
+
+        let mut proof = tree.prove(4).unwrap();
+        verify(&proof).unwrap();
+
+        // leaf 4 has no siblings below the root's children, so an index of
+        // 7 walks the same path in a tree of 8 leaves
+        proof.leaf_index = 7;
+        assert_eq!(verify(&proof).unwrap_err().kind, ErrorKind::IndexOutOfRange);
+
+        proof.leaf_index = 4;
+        proof.leaf_count = 8;
+        assert_eq!(verify(&proof).unwrap_err().kind, ErrorKind::InvalidIndex);
+    }
+
+    #[test]
+    fn test_multi_proof() {
+        let index_sets: [&[usize]; 5] = [&[0], &[12], &[0, 1], &[3, 4, 9, 12], &[12, 2, 2, 7]];
+
+        for mode in [Mode::Plain, Mode::Rfc6962].iter() {
+            for leaf_count in [1, 2, 10, 13].iter() {
+                let leaves = random_leaves(*leaf_count);
+                let tree = MerkleTree::<D>::from_data(convert(leaves.clone()), *mode);
+                let root = tree.root().unwrap();
+
+                for indices in index_sets.iter() {
+                    let indices: Vec<usize> = indices.iter().map(|i| i % leaf_count).collect();
+                    let proof = tree.prove_multi(&indices).unwrap();
+                    let data: Vec<Leaf> = proof
+                        .leaf_indices
+                        .iter()
+                        .map(|i| Leaf::Data(&leaves[*i]))
+                        .collect();
+
+                    tree.verify_multi(&proof).unwrap();
+                    proof.verify_against_root::<D>(&root, &data, *mode).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_multi_proof_compression() {
+        let tree = MerkleTree::<D>::from(convert(random_leaves(16)));
+
+        let proof = tree.prove_multi(&[0, 1, 2, 3]).unwrap();
+        assert_eq!(proof.nodes.len(), 2);
+
+        let proof = tree.prove_multi(&(0..16).collect::<Vec<_>>()).unwrap();
+        assert!(proof.nodes.is_empty());
+
+        let proof = tree.prove_multi(&[5]).unwrap();
+        assert_eq!(proof.nodes.len(), tree.height - 1);
+    }
+
+    #[test]
+    fn test_multi_proof_errors() {
+        let leaves = random_leaves(10);
+        let tree = MerkleTree::<D>::from(convert(leaves.clone()));
+        let root = tree.root().unwrap();
+        let verify = |proof: &MultiProof, kind: ErrorKind| {
+            let data: Vec<Leaf> = proof
+                .leaf_indices
+                .iter()
+                .map(|i| Leaf::Data(&leaves[*i % leaves.len()]))
+                .collect();
+            match proof.verify_against_root::<D>(&root, &data, Mode::Plain) {
+                Ok(()) => panic!("Proof verification should return an error"),
+                Err(err) => assert_eq!(err.kind, kind),
+            }
+        };
+
+        assert_eq!(
+            tree.prove_multi(&[]).unwrap_err().kind,
+            ErrorKind::InvalidLength
+        );
+        assert_eq!(
+            tree.prove_multi(&[3, 10]).unwrap_err().kind,
+            ErrorKind::IndexOutOfRange
+        );
+
+        let mut proof = tree.prove_multi(&[2, 5]).unwrap();
+        proof.nodes.pop();
+        verify(&proof, ErrorKind::InvalidLength);
+
+        let mut proof = tree.prove_multi(&[2, 5]).unwrap();
+        proof.nodes.push(tree.get(0).unwrap());
+        verify(&proof, ErrorKind::InvalidLength);
+
+        let mut proof = tree.prove_multi(&[2, 5]).unwrap();
+        proof.nodes[0] = tree.get(0).unwrap();
+        verify(&proof, ErrorKind::InvalidHash);
+
+        let mut proof = tree.prove_multi(&[2, 5]).unwrap();
+        proof.leaf_indices.reverse();
+        verify(&proof, ErrorKind::InvalidIndex);
+
+        let mut proof = tree.prove_multi(&[2, 5]).unwrap();
+        proof.leaf_count = usize::MAX;
+        verify(&proof, ErrorKind::InvalidLength);
+    }
+
+    #[test]
+    fn test_range_proof() {
+        for mode in [Mode::Plain, Mode::Rfc6962].iter() {
+            let leaves = random_leaves(13);
+            let tree = MerkleTree::<D>::from_data(convert(leaves.clone()), *mode);
+            let root = tree.root().unwrap();
+
+            for (start, end) in [(0, 1), (0, 13), (3, 9), (12, 13)].iter() {
+                let proof = tree.prove_range(*start..*end).unwrap();
+                let data: Vec<Leaf> = leaves[*start..*end]
+                    .iter()
+                    .map(|leaf| Leaf::Data(leaf))
+                    .collect();
+
+                proof.verify_against_root::<D>(&root, &data, *mode).unwrap();
+                assert!(proof
+                    .verify_against_root::<D>(&root, &data[1..], *mode)
+                    .is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_range_proof_errors() {
+        let leaves = random_leaves(16);
+        let tree = MerkleTree::<D>::from(convert(leaves.clone()));
+        let root = tree.root().unwrap();
+
+        let proof = tree.prove_range(4..12).unwrap();
+        assert_eq!(proof.nodes.len(), 2);
+
+        let mut data: Vec<Leaf> = leaves[4..12].iter().map(|l| Leaf::Data(l)).collect();
+        data.swap(0, 1);
+        assert_eq!(
+            proof
+                .verify_against_root::<D>(&root, &data, Mode::Plain)
+                .unwrap_err()
+                .kind,
+            ErrorKind::InvalidHash
+        );
+
+        assert_eq!(
+            tree.prove_range(3..3).unwrap_err().kind,
+            ErrorKind::InvalidLength
+        );
+        assert_eq!(
+            tree.prove_range(3..17).unwrap_err().kind,
+            ErrorKind::IndexOutOfRange
+        );
+    }
+
+    #[test]
+    fn test_push() {
+        for mode in [Mode::Plain, Mode::Rfc6962].iter() {
+            let leaves: Vec<Array> = random_leaves(17)
+                .iter()
+                .map(|data| mode.hash_leaf(&mut D::new(), data))
+                .collect();
+            let mut tree = MerkleTree::<D>::from_hashes(&[], *mode).unwrap();
+
+            for (index, leaf) in leaves.iter().enumerate() {
+                tree.push(leaf).unwrap();
+
+                let expected = MerkleTree::<D>::from_hashes(&leaves[..=index], *mode).unwrap();
+                assert!(tree.built());
+                assert_eq!(tree.leaf_count, index + 1);
+                assert_eq!(tree.height, expected.height);
+                assert_eq!(tree.hashes[..], expected.hashes[..]);
+            }
+
+            assert!(tree.push(&vec![0; D::output_size() + 1]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_consistency_proof() {
+        let leaves = random_leaves(20);
+        let mut roots = Vec::new();
+        let mut tree = MerkleTree::<D>::from_hashes(&[], Mode::Rfc6962).unwrap();
+
+        for leaf in leaves.iter() {
+            tree.push(&tree.hash_leaf(leaf)).unwrap();
+            roots.push(tree.root().unwrap());
+        }
+
+        for new_size in 1..=leaves.len() {
+            let tree =
+                MerkleTree::<D>::from_data(convert(leaves[..new_size].to_vec()), Mode::Rfc6962);
+
+            for old_size in 1..=new_size {
+                let proof = tree.prove_consistency(old_size).unwrap();
+                proof
+                    .verify_against_roots::<D>(&roots[old_size - 1], &roots[new_size - 1])
+                    .unwrap();
+
+                if old_size < new_size {
+                    assert!(proof
+                        .verify_against_roots::<D>(&roots[new_size - 1], &roots[new_size - 1])
+                        .is_err());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_consistency_proof_errors() {
+        let leaves = random_leaves(11);
+        let old = MerkleTree::<D>::from_data(convert(leaves[..6].to_vec()), Mode::Rfc6962);
+        let new = MerkleTree::<D>::from_data(convert(leaves.clone()), Mode::Rfc6962);
+        let (old_root, new_root) = (old.root().unwrap(), new.root().unwrap());
+        let verify = |proof: &ConsistencyProof, kind: ErrorKind| match proof
+            .verify_against_roots::<D>(&old_root, &new_root)
+        {
+            Ok(()) => panic!("Proof verification should return an error"),
+            Err(err) => assert_eq!(err.kind, kind),
+        };
+
+        let mut proof = new.prove_consistency(6).unwrap();
+        proof.nodes[1] = new.get(0).unwrap();
+        verify(&proof, ErrorKind::InvalidHash);
+
+        let mut proof = new.prove_consistency(6).unwrap();
+        proof.nodes.pop();
+        verify(&proof, ErrorKind::InvalidLength);
+
+        let mut proof = new.prove_consistency(6).unwrap();
+        proof.old_size = 5;
+        verify(&proof, ErrorKind::InvalidLength);
+
+        assert_eq!(
+            new.prove_consistency(12).unwrap_err().kind,
+            ErrorKind::IndexOutOfRange
+        );
+
+        let plain = MerkleTree::<D>::from(convert(leaves.clone()));
+        assert_eq!(
+            plain.prove_consistency(6).unwrap_err().kind,
+            ErrorKind::UnsupportedMode
+        );
+    }
+
+    #[test]
+    fn test_from_root() {
+        for mode in [Mode::Plain, Mode::Rfc6962].iter() {
+            let source = MerkleTree::<D>::from_data(convert(random_leaves(13)), *mode);
+            let root = source.root().unwrap();
+            let mut tree = MerkleTree::<D>::from_root(13, &root, *mode).unwrap();
+
+            assert!(!tree.built());
+            assert_eq!(tree.root(), Some(root.clone()));
+            assert_eq!(tree.prove(3).unwrap_err().kind, ErrorKind::PartialProof);
+            tree.verify(&source.prove(3).unwrap()).unwrap();
+
+            tree.insert_proof(&source.prove(3).unwrap()).unwrap();
+            let proof = tree.prove(3).unwrap();
+            assert!(!proof.partial);
+            assert_eq!(proof.path, source.prove(3).unwrap().path);
+            source.verify(&proof).unwrap();
+
+            for leaf in 0..13 {
+                tree.insert_proof(&source.prove(leaf).unwrap()).unwrap();
+            }
+
+            assert!(tree.built());
+            assert_eq!(tree.hashes[..], source.hashes[..]);
+        }
+
+        assert!(MerkleTree::<D>::from_root(0, &vec![0; D::output_size()], Mode::Plain).is_err());
+        assert!(MerkleTree::<D>::from_root(4, &vec![0; 3], Mode::Plain).is_err());
+    }
+
+    #[test]
+    fn test_insert_invalid_proof() {
+        let source = MerkleTree::<D>::from(convert(random_leaves(10)));
+        let mut tree =
+            MerkleTree::<D>::from_root(10, &source.root().unwrap(), Mode::Plain).unwrap();
+
+        let mut proof = source.prove(4).unwrap();
+        proof.leaf_hash = source.get(5).unwrap();
+        assert_eq!(
+            tree.insert_proof(&proof).unwrap_err().kind,
+            ErrorKind::InvalidHash
+        );
+
+        let mut proof = source.prove(4).unwrap();
+        proof.path.pop();
+        assert_eq!(
+            tree.insert_proof(&proof).unwrap_err().kind,
+            ErrorKind::InvalidLength
+        );
+
+        assert!((0..tree.bitmap.len() - 1).all(|index| !tree.has(index)));
+    }
+
+    #[test]
+    fn test_diff() {
+        for leaf_count in [1, 2, 10, 13, 16].iter() {
+            let leaves = random_leaves(*leaf_count);
+            let tree = MerkleTree::<D>::from(convert(leaves.clone()));
+            assert!(tree.diff(&tree).unwrap().is_empty());
+
+            let changed: Vec<usize> = (0..*leaf_count).filter(|i| i % 3 == 0).collect();
+            let mut modified = leaves.clone();
+            changed.iter().for_each(|i| modified[*i][0] ^= 0xff);
+
+            let other = MerkleTree::<D>::from(convert(modified));
+            assert_eq!(tree.diff(&other).unwrap(), changed);
+            assert_eq!(other.diff(&tree).unwrap(), changed);
+        }
+
+        let tree = MerkleTree::<D>::from(convert(random_leaves(4)));
+        let other = MerkleTree::<D>::from(convert(random_leaves(5)));
+        assert!(tree.diff(&other).is_err());
+    }
+
+    #[test]
+    fn test_diff_exchange() {
+        let leaves = random_leaves(16);
+        let tree = MerkleTree::<D>::from(convert(leaves.clone()));
+        let mut modified = leaves.clone();
+        modified[9][0] ^= 0xff;
+        let other = MerkleTree::<D>::from(convert(modified));
+
+        let mut diff = TreeDiff::new(tree.leaf_count);
+        let mut exchanged = 0;
+
+        while !diff.is_done() {
+            let request = diff.request();
+            let remote = other.node_hashes(&request).unwrap();
+            exchanged += remote.len();
+            diff.update(&tree.node_hashes(&request).unwrap(), &remote)
+                .unwrap();
+        }
+
+        assert_eq!(diff.leaves(), &[9]);
+        assert_eq!(exchanged, 1 + 2 * (tree.height - 1));
+
+        let mut request = TreeDiff::new(tree.leaf_count).request();
+        request.leaf_count = 15;
+        assert!(other.node_hashes(&request).is_err());
+    }
+
     #[test]
     fn test_verify_partial_proof() {
         let leaf_count = 10;
-        let tree = MerkleTree::<D>::from(random_leaves(leaf_count).iter());
+        let tree = MerkleTree::<D>::from(convert(random_leaves(leaf_count)));
 
         for leaf in 0..leaf_count {
             let mut proof = tree.prove(leaf).unwrap();
@@ -403,7 +1257,7 @@ mod tests {
     #[test]
     fn test_verify_errors() {
         let leaf_count = 10;
-        let tree = MerkleTree::<D>::from(random_leaves(leaf_count).iter());
+        let tree = MerkleTree::<D>::from(convert(random_leaves(leaf_count)));
         let verify = |proof: &Proof, kind: ErrorKind| match tree.verify(&proof) {
             Ok(()) => panic!("Proof verification should return an error"),
             Err(err) => {
@@ -415,6 +1269,7 @@ mod tests {
 
         let mut proof = Proof {
             leaf_index: 10,
+            leaf_count: 10,
             leaf_hash: Array::new(),
             path: vec![Some(Array::new())],
             partial: true,
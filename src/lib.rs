@@ -6,7 +6,9 @@ extern crate crypto;
 extern crate fs2;
 extern crate futures;
 extern crate indexmap;
+extern crate memmap;
 extern crate merkle_tree;
+extern crate rand;
 extern crate serde;
 extern crate streaming_iterator;
 
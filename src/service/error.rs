@@ -14,6 +14,9 @@ pub enum ErrorKind {
     StorageMapError(StorageMapErrorKind),
     StorageAlreadyExists,
     StorageDoesNotExist,
+    /// Location locked by another storage or process, or overlapping the
+    /// locations of an open storage
+    LockConflict(String),
     MailboxError(MailboxError),
 }
 
@@ -31,15 +34,24 @@ impl From<bincode::Error> for Error {
     }
 }
 
+impl From<StorageErrorKind> for ErrorKind {
+    fn from(kind: StorageErrorKind) -> Self {
+        match kind {
+            StorageErrorKind::Locked(location) => ErrorKind::LockConflict(location),
+            kind => ErrorKind::StorageError(kind),
+        }
+    }
+}
+
 impl From<StorageErrorKind> for Error {
     fn from(kind: StorageErrorKind) -> Self {
-        Self::new(ErrorKind::StorageError(kind))
+        Self::new(kind.into())
     }
 }
 
 impl From<error::Error<StorageErrorKind>> for Error {
     fn from(error: error::Error<StorageErrorKind>) -> Self {
-        Self::new(ErrorKind::StorageError(error.kind))
+        Self::new(error.kind.into())
     }
 }
 
@@ -48,7 +60,7 @@ impl From<error::Error<StorageMapErrorKind>> for Error {
         let kind = if let StorageMapErrorKind::IoError(s) = error.kind {
             ErrorKind::IoError(s)
         } else if let StorageMapErrorKind::StorageError(kind) = error.kind {
-            ErrorKind::StorageError(kind)
+            kind.into()
         } else {
             ErrorKind::StorageMapError(error.kind)
         };
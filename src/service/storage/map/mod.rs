@@ -1,38 +1,110 @@
 mod serialize;
+#[macro_use]
 mod version;
 
 use std::path::Path;
 
 use actix::*;
-use merkle_tree::proof::Provable;
+use merkle_tree::digest::Algorithm;
+use merkle_tree::mode::Mode;
+use merkle_tree::proof::{MultiProvable, Provable};
+use merkle_tree::Array;
 
 use self::serialize::{deserialize_from, serialize_into};
 use service::error::{Error, ErrorKind};
-use service::storage::map::version::{StorageMapVersion, VersionedStorageMap};
+use service::storage::map::version::{
+    SavedVersionedStorageMap, StorageMapV1, StorageMapV2, StorageMapVersion, VersionedStorageMap,
+};
 use service::storage::message;
 use service::Result;
+use storage::generic::options::{CreateOptions, LoadOptions};
+use storage::map::chunk::Layout;
+use storage::relocation::Relocation;
+use storage::resource::OpenMode;
+use storage::space::Quota;
 
 pub struct StorageMapActor {
     holder: Option<VersionedStorageMap>,
+    /// Limit of bytes allocated for a created storage
+    quota: Option<Quota>,
 }
 
 impl StorageMapActor {
     pub fn new() -> Self {
-        StorageMapActor { holder: None }
+        StorageMapActor {
+            holder: None,
+            quota: None,
+        }
+    }
+
+    pub fn with_quota(quota: Quota) -> Self {
+        StorageMapActor {
+            holder: None,
+            quota: Some(quota),
+        }
     }
 
-    fn create(name: String, resources: Vec<(String, usize)>) -> Result<VersionedStorageMap> {
-        let storage_map = StorageMapVersion::new(name, resources)?;
+    fn create(
+        name: String,
+        resources: message::Resources,
+        layout: &Layout,
+        algorithm: Algorithm,
+        mode: Mode,
+        options: &CreateOptions<()>,
+    ) -> Result<VersionedStorageMap> {
+        let storage_map =
+            StorageMapVersion::new(name, resources, layout, algorithm, mode, options)?;
         let holder = VersionedStorageMap::wrap(storage_map);
         Ok(holder)
     }
 
-    fn load(location: &String) -> Result<VersionedStorageMap> {
+    fn download(
+        name: String,
+        resources: message::Resources,
+        layout: &Layout,
+        algorithm: Algorithm,
+        mode: Mode,
+        hashes: Vec<Array>,
+        options: &CreateOptions<()>,
+    ) -> Result<VersionedStorageMap> {
+        let storage_map = StorageMapVersion::from_hashes(
+            name, resources, layout, algorithm, mode, hashes, options,
+        )?;
+        let holder = VersionedStorageMap::wrap(storage_map);
+        Ok(holder)
+    }
+
+    fn load(
+        location: &String,
+        relocation: Option<Relocation>,
+        open_mode: OpenMode,
+    ) -> Result<VersionedStorageMap> {
+        let options = LoadOptions {
+            mode: open_mode,
+            relocation,
+            resource: (),
+        };
         let path = Path::new(location);
-        let holder = deserialize_from::<VersionedStorageMap>(path)?;
+        let saved = match deserialize_from::<SavedVersionedStorageMap>(path) {
+            Ok(saved) => saved,
+            // Maps were once saved without their version
+            Err(error) => match deserialize_from::<StorageMapV1>(path) {
+                Ok(map) => SavedVersionedStorageMap::V1(map),
+                Err(_) => return Err(error.into()),
+            },
+        };
+        let holder = saved.open(&options)?;
         Ok(holder)
     }
 
+    fn create_options(&self, open_mode: OpenMode) -> CreateOptions<()> {
+        CreateOptions {
+            mode: open_mode,
+            quota: self.quota,
+            resource: (),
+        }
+    }
+
     fn try_unwrap(&self) -> Result<&StorageMapVersion> {
         match &self.holder {
             Some(h) => h.try_unwrap(),
@@ -44,7 +116,8 @@ impl StorageMapActor {
 impl From<StorageMapVersion> for StorageMapActor {
     fn from(map: StorageMapVersion) -> Self {
         Self {
-            holder: Some(VersionedStorageMap::V1(map)),
+            holder: Some(VersionedStorageMap::V2(map)),
+            quota: None,
         }
     }
 }
@@ -61,8 +134,38 @@ impl Handler<message::Create> for StorageMapActor {
             return Err(Error::new(ErrorKind::StorageAlreadyExists));
         }
 
-        self.holder = Some(StorageMapActor::create(msg.id, msg.resources)?);
-        Ok(self.try_unwrap()?.name().clone())
+        let holder = StorageMapActor::create(
+            msg.id,
+            msg.resources,
+            &msg.layout,
+            msg.algorithm,
+            msg.mode,
+            &self.create_options(msg.open_mode),
+        )?;
+        self.holder = Some(holder);
+        Ok(with_storage_map!(self.try_unwrap()?, map => map.name().clone()))
+    }
+}
+
+impl Handler<message::Download> for StorageMapActor {
+    type Result = <message::Download as Message>::Result;
+
+    fn handle(&mut self, msg: message::Download, _ctx: &mut Self::Context) -> Self::Result {
+        if self.holder.is_some() {
+            return Err(Error::new(ErrorKind::StorageAlreadyExists));
+        }
+
+        let holder = StorageMapActor::download(
+            msg.id,
+            msg.resources,
+            &msg.layout,
+            msg.algorithm,
+            msg.mode,
+            msg.hashes,
+            &self.create_options(OpenMode::ReadWrite),
+        )?;
+        self.holder = Some(holder);
+        Ok(with_storage_map!(self.try_unwrap()?, map => map.name().clone()))
     }
 }
 
@@ -74,8 +177,12 @@ impl Handler<message::Load> for StorageMapActor {
             return Err(Error::new(ErrorKind::StorageAlreadyExists));
         }
 
-        self.holder = Some(StorageMapActor::load(&msg.location)?);
-        Ok(self.try_unwrap()?.name().clone())
+        self.holder = Some(StorageMapActor::load(
+            &msg.location,
+            msg.relocation,
+            msg.open_mode,
+        )?);
+        Ok(with_storage_map!(self.try_unwrap()?, map => map.name().clone()))
     }
 }
 
@@ -83,18 +190,84 @@ impl Handler<message::Save> for StorageMapActor {
     type Result = <message::Save as Message>::Result;
 
     fn handle(&mut self, msg: message::Save, _ctx: &mut Self::Context) -> Self::Result {
+        match &self.holder {
+            Some(holder) => serialize_into(holder, Path::new(&msg.location))?,
+            None => return Err(Error::new(ErrorKind::StorageDoesNotExist)),
+        }
+        Ok(())
+    }
+}
+
+impl Handler<message::Hashes> for StorageMapActor {
+    type Result = <message::Hashes as Message>::Result;
+
+    fn handle(&mut self, _msg: message::Hashes, _ctx: &mut Self::Context) -> Self::Result {
+        let map = self.try_unwrap()?;
+        Ok(with_storage_map!(map, map => map.hashes()))
+    }
+}
+
+impl Handler<message::Root> for StorageMapActor {
+    type Result = <message::Root as Message>::Result;
+
+    fn handle(&mut self, _msg: message::Root, _ctx: &mut Self::Context) -> Self::Result {
+        let map = self.try_unwrap()?;
+        Ok(with_storage_map!(map, map => map.root()))
+    }
+}
+
+impl Handler<message::Tree> for StorageMapActor {
+    type Result = <message::Tree as Message>::Result;
+
+    fn handle(&mut self, _msg: message::Tree, _ctx: &mut Self::Context) -> Self::Result {
+        let map = self.try_unwrap()?;
+        Ok(with_storage_map!(map, map => map.storage().tree().cloned()))
+    }
+}
+
+impl Handler<message::ApplyModes> for StorageMapActor {
+    type Result = <message::ApplyModes as Message>::Result;
+
+    fn handle(&mut self, _msg: message::ApplyModes, _ctx: &mut Self::Context) -> Self::Result {
         let map = self.try_unwrap()?;
-        serialize_into(map, Path::new(&msg.location))?;
+        with_storage_map!(map, map => map.storage().apply_modes())?;
         Ok(())
     }
 }
 
+impl Handler<message::Locations> for StorageMapActor {
+    type Result = <message::Locations as Message>::Result;
+
+    fn handle(&mut self, _msg: message::Locations, _ctx: &mut Self::Context) -> Self::Result {
+        let map = self.try_unwrap()?;
+        Ok(with_storage_map!(map, map => map.storage().locations()))
+    }
+}
+
+impl Handler<message::Allocated> for StorageMapActor {
+    type Result = <message::Allocated as Message>::Result;
+
+    fn handle(&mut self, _msg: message::Allocated, _ctx: &mut Self::Context) -> Self::Result {
+        let map = self.try_unwrap()?;
+        Ok(with_storage_map!(map, map => map.storage().allocated()))
+    }
+}
+
+impl Handler<message::NodeHashes> for StorageMapActor {
+    type Result = <message::NodeHashes as Message>::Result;
+
+    fn handle(&mut self, msg: message::NodeHashes, _ctx: &mut Self::Context) -> Self::Result {
+        let map = self.try_unwrap()?;
+        Ok(with_storage_map!(map, map => map.node_hashes(&msg.request))?)
+    }
+}
+
 impl Handler<message::ReadChunk> for StorageMapActor {
     type Result = <message::ReadChunk as Message>::Result;
 
     fn handle(&mut self, msg: message::ReadChunk, _ctx: &mut Self::Context) -> Self::Result {
         let map = self.try_unwrap()?;
-        let result = map.read_chunk(msg.chunk)?;
+        let result = with_storage_map!(map, map => map.read_chunk(msg.chunk))?;
         Ok(result)
     }
 }
@@ -105,7 +278,7 @@ impl Handler<message::WriteChunk> for StorageMapActor {
     fn handle(&mut self, msg: message::WriteChunk, _ctx: &mut Self::Context) -> Self::Result {
         match &mut self.holder {
             Some(ref mut holder) => holder.with_mut(|map| {
-                map.write_chunk(msg.chunk, &msg.data)?;
+                with_storage_map!(map, map => map.write_chunk(msg.chunk, &msg.data))?;
                 Ok(())
             }),
             None => Err(Error::new(ErrorKind::StorageDoesNotExist)),
@@ -120,7 +293,7 @@ impl Handler<message::HasChunk> for StorageMapActor {
 
     fn handle(&mut self, msg: message::HasChunk, _ctx: &mut Self::Context) -> Self::Result {
         let map = self.try_unwrap()?;
-        Ok(map.has_chunk(msg.chunk))
+        Ok(with_storage_map!(map, map => map.has_chunk(msg.chunk)))
     }
 }
 
@@ -129,7 +302,16 @@ impl Handler<message::HasPiece> for StorageMapActor {
 
     fn handle(&mut self, msg: message::HasPiece, _ctx: &mut Self::Context) -> Self::Result {
         let map = self.try_unwrap()?;
-        Ok(map.has_piece(msg.piece))
+        Ok(with_storage_map!(map, map => map.has_piece(msg.piece)))
+    }
+}
+
+impl Handler<message::PickChunks> for StorageMapActor {
+    type Result = <message::PickChunks as Message>::Result;
+
+    fn handle(&mut self, msg: message::PickChunks, _ctx: &mut Self::Context) -> Self::Result {
+        let map = self.try_unwrap()?;
+        Ok(with_storage_map!(map, map => map.pick_chunks(msg.count, &msg.strategy)))
     }
 }
 
@@ -138,7 +320,7 @@ impl Handler<message::Prove> for StorageMapActor {
 
     fn handle(&mut self, msg: message::Prove, _ctx: &mut Self::Context) -> Self::Result {
         let map = self.try_unwrap()?;
-        Ok(map.prove(msg.leaf_index)?)
+        Ok(with_storage_map!(map, map => map.prove(msg.leaf_index))?)
     }
 }
 
@@ -147,7 +329,44 @@ impl Handler<message::VerifyProof> for StorageMapActor {
 
     fn handle(&mut self, msg: message::VerifyProof, _ctx: &mut Self::Context) -> Self::Result {
         let map = self.try_unwrap()?;
-        map.verify(&msg.proof)?;
+        with_storage_map!(map, map => map.verify(&msg.proof))?;
         Ok(())
     }
 }
+
+impl Handler<message::ProveMulti> for StorageMapActor {
+    type Result = <message::ProveMulti as Message>::Result;
+
+    fn handle(&mut self, msg: message::ProveMulti, _ctx: &mut Self::Context) -> Self::Result {
+        let map = self.try_unwrap()?;
+        Ok(with_storage_map!(map, map => map.prove_multi(&msg.leaf_indices))?)
+    }
+}
+
+impl Handler<message::VerifyMultiProof> for StorageMapActor {
+    type Result = <message::VerifyMultiProof as Message>::Result;
+
+    fn handle(&mut self, msg: message::VerifyMultiProof, _ctx: &mut Self::Context) -> Self::Result {
+        let map = self.try_unwrap()?;
+        with_storage_map!(map, map => map.verify_multi(&msg.proof))?;
+        Ok(())
+    }
+}
+
+impl Handler<message::Challenge> for StorageMapActor {
+    type Result = <message::Challenge as Message>::Result;
+
+    fn handle(&mut self, msg: message::Challenge, _ctx: &mut Self::Context) -> Self::Result {
+        let map = self.try_unwrap()?;
+        Ok(with_storage_map!(map, map => map.challenge(msg.count)))
+    }
+}
+
+impl Handler<message::Respond> for StorageMapActor {
+    type Result = <message::Respond as Message>::Result;
+
+    fn handle(&mut self, msg: message::Respond, _ctx: &mut Self::Context) -> Self::Result {
+        let map = self.try_unwrap()?;
+        Ok(with_storage_map!(map, map => map.respond(&msg.challenge))?)
+    }
+}
@@ -1,21 +1,186 @@
-use serde::{Deserialize, Serialize};
+use merkle_tree::digest::blake2b::Blake2b;
+use merkle_tree::digest::sha256::Sha256;
+use merkle_tree::digest::sha3::Sha3_256;
+use merkle_tree::digest::sha512::Sha512;
+use merkle_tree::digest::Algorithm;
+use merkle_tree::mode::Mode;
+use merkle_tree::Array;
+use serde::{Deserialize, Serialize, Serializer};
 
 use service::error::Error;
+use service::storage::message::Resources;
 use storage::file::resource;
+use storage::generic::options::{CreateOptions, LoadOptions};
+#[cfg(unix)]
+use storage::generic::resource::PositionalResourcePtr;
+#[cfg(not(unix))]
+use storage::generic::resource::SharedResourcePtr;
 use storage::generic::GenericStorage;
-use storage::map::StorageMap;
+use storage::map::chunk::Layout;
+use storage::map::{LegacySavedStorageMap, SavedStorageMap, StorageMap};
 
-pub type StorageV1 = GenericStorage<resource::FileResource>;
-pub type StorageMapV1 = StorageMap<StorageV1>;
-pub type StorageMapVersion = StorageMapV1;
+/// Evaluate an expression for the inner `StorageMap` of a `StorageMapV2`,
+/// regardless of its digest algorithm
+macro_rules! with_storage_map {
+    ($map:expr, $inner:ident => $body:expr) => {
+        match $map {
+            StorageMapV2::Sha512($inner) => $body,
+            StorageMapV2::Sha256($inner) => $body,
+            StorageMapV2::Blake2b($inner) => $body,
+            StorageMapV2::Sha3_256($inner) => $body,
+        }
+    };
+}
+
+#[cfg(unix)]
+pub type StorageV2 =
+    GenericStorage<resource::FileResource, PositionalResourcePtr<resource::FileResource>>;
+#[cfg(not(unix))]
+pub type StorageV2 =
+    GenericStorage<resource::FileResource, SharedResourcePtr<resource::FileResource>>;
+pub type StorageMapVersion = StorageMapV2;
+
+#[derive(Serialize)]
+pub enum StorageMapV2 {
+    Sha512(StorageMap<StorageV2, Sha512>),
+    Sha256(StorageMap<StorageV2, Sha256>),
+    Blake2b(StorageMap<StorageV2, Blake2b>),
+    Sha3_256(StorageMap<StorageV2, Sha3_256>),
+}
+
+/// Deserialized `StorageMapV2`, before its storage is opened
+#[derive(Deserialize)]
+pub enum SavedStorageMapV2 {
+    Sha512(SavedStorageMap<Sha512>),
+    Sha256(SavedStorageMap<Sha256>),
+    Blake2b(SavedStorageMap<Blake2b>),
+    Sha3_256(SavedStorageMap<Sha3_256>),
+}
+
+impl StorageMapV2 {
+    fn storage(
+        name: String,
+        resources: Resources,
+        options: &CreateOptions<()>,
+    ) -> Result<StorageV2, Error> {
+        let storage = match resources {
+            Resources::Locations(items) => StorageV2::with_options(name, items, options)?,
+            Resources::Dir(root) => StorageV2::from_dir(name, &root, options)?,
+            Resources::Tree(root, tree) => StorageV2::from_tree(name, &root, tree, options)?,
+        };
+        Ok(storage)
+    }
+
+    pub fn new(
+        name: String,
+        resources: Resources,
+        layout: &Layout,
+        algorithm: Algorithm,
+        mode: Mode,
+        options: &CreateOptions<()>,
+    ) -> Result<Self, Error> {
+        let storage = Self::storage(name, resources, options)?;
+        let map = match algorithm {
+            Algorithm::Sha512 => {
+                StorageMapV2::Sha512(StorageMap::from_storage(storage, layout, mode)?)
+            }
+            Algorithm::Sha256 => {
+                StorageMapV2::Sha256(StorageMap::from_storage(storage, layout, mode)?)
+            }
+            Algorithm::Blake2b => {
+                StorageMapV2::Blake2b(StorageMap::from_storage(storage, layout, mode)?)
+            }
+            Algorithm::Sha3_256 => {
+                StorageMapV2::Sha3_256(StorageMap::from_storage(storage, layout, mode)?)
+            }
+        };
+        Ok(map)
+    }
+
+    pub fn from_hashes(
+        name: String,
+        resources: Resources,
+        layout: &Layout,
+        algorithm: Algorithm,
+        mode: Mode,
+        hashes: Vec<Array>,
+        options: &CreateOptions<()>,
+    ) -> Result<Self, Error> {
+        let storage = Self::storage(name, resources, options)?;
+        let map = match algorithm {
+            Algorithm::Sha512 => StorageMapV2::Sha512(StorageMap::from_storage_hashes(
+                storage, layout, mode, hashes,
+            )?),
+            Algorithm::Sha256 => StorageMapV2::Sha256(StorageMap::from_storage_hashes(
+                storage, layout, mode, hashes,
+            )?),
+            Algorithm::Blake2b => StorageMapV2::Blake2b(StorageMap::from_storage_hashes(
+                storage, layout, mode, hashes,
+            )?),
+            Algorithm::Sha3_256 => StorageMapV2::Sha3_256(StorageMap::from_storage_hashes(
+                storage, layout, mode, hashes,
+            )?),
+        };
+        Ok(map)
+    }
+}
+
+impl SavedStorageMapV2 {
+    pub fn open(self, options: &LoadOptions<()>) -> Result<StorageMapV2, Error> {
+        let map = match self {
+            SavedStorageMapV2::Sha512(map) => StorageMapV2::Sha512(map.open(options)?),
+            SavedStorageMapV2::Sha256(map) => StorageMapV2::Sha256(map.open(options)?),
+            SavedStorageMapV2::Blake2b(map) => StorageMapV2::Blake2b(map.open(options)?),
+            SavedStorageMapV2::Sha3_256(map) => StorageMapV2::Sha3_256(map.open(options)?),
+        };
+        Ok(map)
+    }
+}
 
-#[derive(Serialize, Deserialize)]
+/// Map of a single storage, saved before digests, hashing modes, layouts
+/// and resource sizes were recorded. Upgraded to a `StorageMapV2` on load.
+pub type StorageMapV1 = LegacySavedStorageMap;
+
+/// Storage map in memory. Maps saved as `V1` are upgraded when loaded and
+/// always saved as the current version.
 pub enum VersionedStorageMap {
+    V2(StorageMapV2),
+}
+
+/// Deserialized `VersionedStorageMap`, opened with `open`
+#[derive(Deserialize)]
+pub enum SavedVersionedStorageMap {
     V1(StorageMapV1),
+    V2(SavedStorageMapV2),
+}
+
+impl SavedVersionedStorageMap {
+    /// Open the storage of the saved map with `options`
+    pub fn open(self, options: &LoadOptions<()>) -> Result<VersionedStorageMap, Error> {
+        let map = match self {
+            SavedVersionedStorageMap::V1(map) => StorageMapV2::Sha512(map.open(options)?),
+            SavedVersionedStorageMap::V2(map) => map.open(options)?,
+        };
+        Ok(VersionedStorageMap::V2(map))
+    }
+}
+
+impl Serialize for VersionedStorageMap {
+    /// Serialize with the variant indices of `SavedVersionedStorageMap`
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            VersionedStorageMap::V2(map) => {
+                serializer.serialize_newtype_variant("VersionedStorageMap", 1, "V2", map)
+            }
+        }
+    }
 }
 
 impl VersionedStorageMap {
-    pub const DEFAULT: fn(StorageMapVersion) -> VersionedStorageMap = VersionedStorageMap::V1;
+    pub const DEFAULT: fn(StorageMapVersion) -> VersionedStorageMap = VersionedStorageMap::V2;
 }
 
 impl VersionedStorageMap {
@@ -25,7 +190,7 @@ impl VersionedStorageMap {
 
     pub fn try_unwrap(&self) -> Result<&StorageMapVersion, Error> {
         match self {
-            VersionedStorageMap::V1(map) => Ok(&map),
+            VersionedStorageMap::V2(map) => Ok(&map),
         }
     }
 
@@ -34,7 +199,7 @@ impl VersionedStorageMap {
         F: Fn(&mut StorageMapVersion) -> Result<R, Error>,
     {
         match self {
-            VersionedStorageMap::V1(ref mut map) => handler(map),
+            VersionedStorageMap::V2(ref mut map) => handler(map),
         }
     }
 }
@@ -1,6 +1,15 @@
 use actix::*;
-use merkle_tree::proof::Proof;
+use merkle_tree::diff::NodeRequest;
+use merkle_tree::digest::Algorithm;
+use merkle_tree::mode::Mode;
+use merkle_tree::proof::{MultiProof, Proof};
 use service::error::Error;
+use storage::map::challenge;
+use storage::map::chunk::Layout;
+use storage::map::picker::Strategy;
+use storage::relocation::Relocation;
+use storage::resource::OpenMode;
+use storage::tree::DirTree;
 
 pub type Array = Vec<u8>;
 
@@ -9,7 +18,7 @@ pub trait ValueHint {
 }
 
 macro_rules! impl_message {
-    ($tt:tt, $v:tt) => {
+    ($tt:tt, $v:ty) => {
         impl ValueHint for $tt {
             type Value = $v;
         }
@@ -20,14 +29,61 @@ macro_rules! impl_message {
     };
 }
 
+/// Resources of a storage being created
+pub enum Resources {
+    /// Files at the given locations, with sizes
+    Locations(Vec<(String, usize)>),
+    /// Directory tree walked at the given root
+    Dir(String),
+    /// Directory tree created under the given destination root
+    Tree(String, DirTree),
+}
+
+impl Resources {
+    /// Bytes of the resources, existing files included. Files of a walked
+    /// directory are only known once the storage is opened.
+    pub fn size(&self) -> usize {
+        match self {
+            Resources::Locations(items) => items.iter().map(|(_, size)| size).sum(),
+            Resources::Dir(_) => 0,
+            Resources::Tree(_, tree) => tree.items().iter().map(|(_, size)| size).sum(),
+        }
+    }
+
+    /// Locations of the resources, or the root of a directory tree
+    pub fn locations(&self) -> Vec<String> {
+        match self {
+            Resources::Locations(items) => items.iter().map(|(l, _)| l.clone()).collect(),
+            Resources::Dir(root) | Resources::Tree(root, _) => vec![root.clone()],
+        }
+    }
+}
+
 pub struct Create {
     pub id: String,
-    pub resources: Vec<(String, usize)>,
+    pub resources: Resources,
+    pub layout: Layout,
+    pub algorithm: Algorithm,
+    pub mode: Mode,
+    /// Read-only storages reject chunk writes
+    pub open_mode: OpenMode,
+}
+
+pub struct Download {
+    pub id: String,
+    pub resources: Resources,
+    pub layout: Layout,
+    pub algorithm: Algorithm,
+    pub mode: Mode,
+    pub hashes: Vec<Array>,
 }
 
 pub struct Load {
     pub id: String,
     pub location: String,
+    /// Mapping of saved resource locations to the current ones
+    pub relocation: Option<Relocation>,
+    pub open_mode: OpenMode,
 }
 
 pub struct Save {
@@ -35,6 +91,39 @@ pub struct Save {
     pub location: String,
 }
 
+pub struct Hashes {
+    pub id: String,
+}
+
+pub struct Root {
+    pub id: String,
+}
+
+pub struct Tree {
+    pub id: String,
+}
+
+pub struct ApplyModes {
+    pub id: String,
+}
+
+pub struct Locations {
+    pub id: String,
+}
+
+pub struct Allocated {
+    pub id: String,
+}
+
+/// Bytes allocated by all storages of a router, including ones reserved
+/// for storages being created
+pub struct TotalAllocated;
+
+pub struct NodeHashes {
+    pub id: String,
+    pub request: NodeRequest,
+}
+
 pub struct ReadChunk {
     pub id: String,
     pub chunk: usize,
@@ -56,6 +145,12 @@ pub struct HasPiece {
     pub piece: usize,
 }
 
+pub struct PickChunks {
+    pub id: String,
+    pub count: usize,
+    pub strategy: Strategy,
+}
+
 pub struct Prove {
     pub id: String,
     pub leaf_index: usize,
@@ -66,12 +161,46 @@ pub struct VerifyProof {
     pub proof: Proof,
 }
 
+pub struct ProveMulti {
+    pub id: String,
+    pub leaf_indices: Vec<usize>,
+}
+
+pub struct VerifyMultiProof {
+    pub id: String,
+    pub proof: MultiProof,
+}
+
+pub struct Challenge {
+    pub id: String,
+    pub count: usize,
+}
+
+pub struct Respond {
+    pub id: String,
+    pub challenge: challenge::Challenge,
+}
+
 impl_message!(Create, String);
+impl_message!(Download, String);
 impl_message!(Load, String);
 impl_message!(Save, ());
+impl_message!(Hashes, Vec<Array>);
+impl_message!(Root, Option<Array>);
+impl_message!(Tree, Option<DirTree>);
+impl_message!(ApplyModes, ());
+impl_message!(Locations, Vec<String>);
+impl_message!(Allocated, usize);
+impl_message!(TotalAllocated, usize);
+impl_message!(NodeHashes, Vec<Option<Array>>);
 impl_message!(ReadChunk, Array);
 impl_message!(WriteChunk, ());
 impl_message!(HasChunk, bool);
 impl_message!(HasPiece, bool);
+impl_message!(PickChunks, Vec<usize>);
 impl_message!(Prove, Proof);
 impl_message!(VerifyProof, ());
+impl_message!(ProveMulti, MultiProof);
+impl_message!(VerifyMultiProof, ());
+impl_message!(Challenge, challenge::Challenge);
+impl_message!(Respond, challenge::Response);
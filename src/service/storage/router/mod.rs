@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::path::Path;
 
 use actix::fut::{err, ok, wrap_future};
 use actix::*;
@@ -7,17 +8,159 @@ use futures::future::Future;
 use service::error::{Error, ErrorKind};
 use service::storage::map::StorageMapActor;
 use service::storage::message::*;
+use service::Result;
+use storage::error::ErrorKind as StorageErrorKind;
+use storage::space::Quota;
 
+#[derive(Default)]
 pub struct StorageRouter {
     actors: HashMap<String, Addr<StorageMapActor>>,
+    /// Resource locations, or tree roots, of open storages
+    locations: HashMap<String, Vec<String>>,
+    /// Bytes allocated by open storages, or reserved for storages being
+    /// created
+    allocated: HashMap<String, usize>,
+    quota: Option<Quota>,
 }
 
 impl StorageRouter {
-    fn spawn(&mut self, name: String) -> Addr<StorageMapActor> {
-        let address: Addr<StorageMapActor> = Arbiter::start(move |_| StorageMapActor::new());
+    pub fn new() -> Self {
+        StorageRouter::default()
+    }
+
+    /// Limit bytes allocated by all storages of the router
+    pub fn with_quota(self, quota: Quota) -> Self {
+        StorageRouter {
+            quota: Some(quota),
+            ..self
+        }
+    }
+
+    fn spawn(&mut self, name: String, quota: Option<Quota>) -> Addr<StorageMapActor> {
+        let address: Addr<StorageMapActor> = Arbiter::start(move |_| match quota {
+            Some(quota) => StorageMapActor::with_quota(quota),
+            None => StorageMapActor::new(),
+        });
         let _ = self.actors.insert(name, address.clone());
         address
     }
+
+    fn remove(&mut self, name: &str) {
+        self.actors.remove(name);
+        self.locations.remove(name);
+        self.allocated.remove(name);
+    }
+
+    fn total_allocated(&self) -> usize {
+        self.allocated.values().sum()
+    }
+
+    /// Reserve up to `size` bytes for a new storage and get the quota left
+    /// for it. Storages never allocate more than their quota, so concurrent
+    /// creation can't exceed the router quota.
+    fn reserve(&mut self, name: &str, size: usize) -> Option<Quota> {
+        let quota = self
+            .quota
+            .map(|Quota(quota)| Quota(quota.saturating_sub(self.total_allocated())));
+        let reserved = match quota {
+            Some(Quota(left)) => size.min(left),
+            None => size,
+        };
+
+        self.allocated.insert(name.to_string(), reserved);
+        quota
+    }
+
+    /// Record bytes allocated by an opened storage in place of its
+    /// reservation, unless they exceed the quota. Sizes of loaded storages
+    /// and walked directories are only known at this point.
+    fn allocate(&mut self, name: &str, size: usize) -> Result<()> {
+        self.allocated.remove(name);
+        if let Some(Quota(quota)) = self.quota {
+            let required = self.total_allocated() + size;
+            if required > quota {
+                return Err(StorageErrorKind::QuotaExceeded(required, quota).into());
+            }
+        }
+
+        self.allocated.insert(name.to_string(), size);
+        Ok(())
+    }
+
+    /// Record locations of a storage, unless any of them overlaps the
+    /// locations of another open storage
+    fn claim(&mut self, name: &str, locations: Vec<String>) -> Result<()> {
+        let conflict = self
+            .locations
+            .values()
+            .flatten()
+            .find(|claimed| locations.iter().any(|location| overlaps(claimed, location)))
+            .cloned();
+
+        match conflict {
+            Some(location) => Err(Error::new(ErrorKind::LockConflict(location))),
+            None => {
+                self.locations.insert(name.to_string(), locations);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Bytes allocated by the storage a message opens, when known before the
+/// storage is opened
+trait Reservation {
+    fn reservation(&self) -> usize;
+}
+
+impl Reservation for Create {
+    fn reservation(&self) -> usize {
+        self.resources.size()
+    }
+}
+
+impl Reservation for Download {
+    fn reservation(&self) -> usize {
+        self.resources.size()
+    }
+}
+
+/// Sizes of saved storages are only known once loaded, when they are
+/// checked against the quota
+impl Reservation for Load {
+    fn reservation(&self) -> usize {
+        0
+    }
+}
+
+/// Locations of the storage a message creates, when known before the
+/// storage is opened
+trait Claim {
+    fn locations(&self) -> Option<Vec<String>>;
+}
+
+impl Claim for Create {
+    fn locations(&self) -> Option<Vec<String>> {
+        Some(self.resources.locations())
+    }
+}
+
+impl Claim for Download {
+    fn locations(&self) -> Option<Vec<String>> {
+        Some(self.resources.locations())
+    }
+}
+
+impl Claim for Load {
+    fn locations(&self) -> Option<Vec<String>> {
+        None
+    }
+}
+
+/// Whether the locations are equal or either one contains the other
+fn overlaps(left: &str, right: &str) -> bool {
+    let (left, right) = (Path::new(left), Path::new(right));
+    left.starts_with(right) || right.starts_with(left)
 }
 
 impl Actor for StorageRouter {
@@ -32,12 +175,7 @@ macro_rules! err {
 
 macro_rules! wrap_future {
     ($Self:tt, $forward:expr) => {
-        wrap_future::<_, $Self>($forward)
-            .map(|result, _a, _c| result.unwrap())
-            .then(|result, _a, _c| match result {
-                Ok(v) => ok(v),
-                Err(e) => err(e),
-            })
+        wrap_future::<_, $Self>($forward.and_then(|result| result))
     }
 }
 
@@ -51,8 +189,49 @@ macro_rules! impl_forward_new {
                     return err!(ErrorKind::StorageAlreadyExists);
                 }
 
-                let address = self.spawn(msg.id.clone());
-                let future = wrap_future!(Self, address.send(msg).map_err(Error::from));
+                // Locations are claimed before any files are created. Saved
+                // locations are only known once loaded, so a conflicting
+                // loaded storage is dropped afterwards.
+                let id = msg.id.clone();
+                let claimed = match Claim::locations(&msg) {
+                    Some(locations) => match self.claim(&id, locations) {
+                        Ok(_) => true,
+                        Err(e) => return Box::new(err(e)),
+                    },
+                    None => false,
+                };
+
+                let quota = self.reserve(&id, msg.reservation());
+                let address = self.spawn(id.clone(), quota);
+                let locations = Locations { id: id.clone() };
+                let allocated = Allocated { id: id.clone() };
+
+                let send = address.send(msg).map_err(Error::from);
+                let future = wrap_future!(Self, send)
+                    .and_then(move |value, _a, _c| {
+                        let locations = address.send(locations).map_err(Error::from);
+                        let allocated = address.send(allocated).map_err(Error::from);
+                        let send = locations
+                            .and_then(|result| result)
+                            .join(allocated.and_then(|result| result));
+                        wrap_future::<_, Self>(send).map(move |info, _a, _c| (value, info))
+                    })
+                    .then(move |result, actor: &mut Self, _c| {
+                        let result = result.and_then(|(value, (locations, allocated))| {
+                            if !claimed {
+                                actor.claim(&id, locations)?;
+                            }
+                            actor.allocate(&id, allocated)?;
+                            Ok(value)
+                        });
+                        match result {
+                            Ok(value) => ok(value),
+                            Err(e) => {
+                                actor.remove(&id);
+                                err(e)
+                            }
+                        }
+                    });
                 Box::new(future)
             }
         }
@@ -78,12 +257,244 @@ macro_rules! impl_forward {
 }
 
 impl_forward_new!(Create);
+impl_forward_new!(Download);
 impl_forward_new!(Load);
 
 impl_forward!(Save);
+impl_forward!(Hashes);
+impl_forward!(Root);
+impl_forward!(Tree);
+impl_forward!(ApplyModes);
+impl_forward!(Locations);
+impl_forward!(Allocated);
+impl_forward!(NodeHashes);
 impl_forward!(ReadChunk);
 impl_forward!(WriteChunk);
 impl_forward!(HasChunk);
 impl_forward!(HasPiece);
+impl_forward!(PickChunks);
 impl_forward!(Prove);
 impl_forward!(VerifyProof);
+impl_forward!(ProveMulti);
+impl_forward!(VerifyMultiProof);
+impl_forward!(Challenge);
+impl_forward!(Respond);
+
+impl Handler<TotalAllocated> for StorageRouter {
+    type Result = <TotalAllocated as Message>::Result;
+
+    fn handle(&mut self, _msg: TotalAllocated, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.total_allocated())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use merkle_tree::digest::Algorithm;
+    use merkle_tree::mode::Mode;
+    use std::fs;
+    use storage::map::chunk::Layout;
+    use storage::resource::OpenMode;
+
+    fn create(id: &str, resources: Resources, open_mode: OpenMode) -> Create {
+        Create {
+            id: id.to_string(),
+            resources,
+            layout: Layout::default(),
+            algorithm: Algorithm::Sha512,
+            mode: Mode::Plain,
+            open_mode,
+        }
+    }
+
+    #[test]
+    fn test_claim() {
+        let mut router = StorageRouter::new();
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+
+        router.claim("files", strings(&["/data/a", "/data/b"])).unwrap();
+        router.claim("tree", strings(&["/tree"])).unwrap();
+        router.claim("other", strings(&["/data/ab", "/treetop"])).unwrap();
+
+        let conflicts = [
+            ("/data/b", "/data/b"),
+            ("/data/a/x", "/data/a"),
+            ("/tree/x/y", "/tree"),
+        ];
+        for (location, claimed) in conflicts.iter() {
+            match router.claim("new", strings(&["/elsewhere", location])) {
+                Ok(_) => panic!("Location {} should conflict", location),
+                Err(error) => match error.kind {
+                    ErrorKind::LockConflict(ref l) if l == claimed => (),
+                    kind => panic!("Invalid error kind: {:?}", kind),
+                },
+            }
+        }
+
+        router.remove("tree");
+        router.claim("new", strings(&["/tree/x/y"])).unwrap();
+    }
+
+    #[test]
+    fn test_reserve() {
+        let mut router = StorageRouter::new().with_quota(Quota(1000));
+
+        assert_eq!(router.reserve("a", 300), Some(Quota(1000)));
+        assert_eq!(router.reserve("b", 900), Some(Quota(700)));
+        assert_eq!(router.total_allocated(), 1000);
+        assert_eq!(router.reserve("c", 100), Some(Quota(0)));
+
+        router.allocated.insert("b".to_string(), 200);
+        assert_eq!(router.total_allocated(), 500);
+        router.remove("a");
+        assert_eq!(router.reserve("d", 0), Some(Quota(800)));
+
+        let mut router = StorageRouter::new();
+        assert_eq!(router.reserve("a", 300), None);
+        assert_eq!(router.total_allocated(), 300);
+    }
+
+    #[test]
+    fn test_create() {
+        let root = std::env::temp_dir().join(format!("golem-router-{}", std::process::id()));
+        let existing = root.join("existing");
+        let created = root.join("created");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(&existing, vec![1u8; 100]).unwrap();
+
+        let dir = Resources::Dir(root.display().to_string());
+        let locations = Resources::Locations(vec![(created.display().to_string(), 100)]);
+
+        let mut system = System::new("test");
+        let router = StorageRouter::new().start();
+        let msg = create("dir", dir, OpenMode::ReadWrite);
+        let result = system.block_on(router.send(msg)).unwrap();
+        assert_eq!(result.unwrap(), "dir");
+        let allocated = system.block_on(router.send(TotalAllocated)).unwrap();
+        assert_eq!(allocated.unwrap(), 100);
+
+        let msg = create("files", locations, OpenMode::ReadWrite);
+        let result = system.block_on(router.send(msg)).unwrap();
+        match result {
+            Ok(_) => panic!("Overlapping storage should not have been created"),
+            Err(error) => match error.kind {
+                ErrorKind::LockConflict(ref l) if *l == root.display().to_string() => (),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+        assert!(!created.exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_load() {
+        use storage::relocation::Relocation;
+
+        let root = std::env::temp_dir().join(format!("golem-router-load-{}", std::process::id()));
+        let location = |name: &str| root.join(name).display().to_string();
+        let data: Vec<u8> = (0..34816).map(|i| (i % 256) as u8).collect();
+        fs::create_dir_all(&root).unwrap();
+        fs::write(location("legacy_map"), data).unwrap();
+        // Saved by the baseline implementation, without a version
+        let legacy = include_bytes!("../../../storage/tests/fixtures/legacy_map.bin");
+        fs::write(location("legacy.map"), &legacy[..]).unwrap();
+
+        let load = |id: &str, name: &str, relocation| Load {
+            id: id.to_string(),
+            location: location(name),
+            relocation,
+            open_mode: OpenMode::ReadOnly,
+        };
+        let root_of = |system: &mut SystemRunner, router: &Addr<StorageRouter>, id: &str| {
+            let msg = Root { id: id.to_string() };
+            system.block_on(router.send(msg)).unwrap().unwrap()
+        };
+
+        let mut system = System::new("test");
+        let router = StorageRouter::new().start();
+        let relocation = Some(Relocation::Root(root.display().to_string()));
+        let msg = load("legacy", "legacy.map", relocation);
+        assert_eq!(
+            system.block_on(router.send(msg)).unwrap().unwrap(),
+            "Legacy"
+        );
+        let legacy_root = root_of(&mut system, &router, "legacy");
+        assert!(legacy_root.is_some());
+
+        let msg = Save {
+            id: "legacy".to_string(),
+            location: location("saved.map"),
+        };
+        system.block_on(router.send(msg)).unwrap().unwrap();
+
+        let other = StorageRouter::new().start();
+        let msg = load("saved", "saved.map", None);
+        system.block_on(other.send(msg)).unwrap().unwrap();
+        assert_eq!(root_of(&mut system, &other, "saved"), legacy_root);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_quota() {
+        let root = std::env::temp_dir().join(format!("golem-router-quota-{}", std::process::id()));
+        let location = |name: &str| root.join(name).display().to_string();
+        fs::create_dir_all(&root).unwrap();
+        fs::write(location("existing"), vec![1u8; 90]).unwrap();
+
+        let mut system = System::new("test");
+        let router = StorageRouter::new().with_quota(Quota(100)).start();
+        let total_allocated = |system: &mut SystemRunner| {
+            let result = system.block_on(router.send(TotalAllocated)).unwrap();
+            result.unwrap()
+        };
+
+        // Existing files count against the quota like new ones
+        let items = vec![(location("existing"), 90), (location("new"), 50)];
+        let msg = create("over", Resources::Locations(items), OpenMode::ReadWrite);
+        match system.block_on(router.send(msg)).unwrap() {
+            Ok(_) => panic!("Storage should not have been created"),
+            Err(error) => match error.kind {
+                ErrorKind::StorageError(StorageErrorKind::QuotaExceeded(140, 100)) => (),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+        assert!(!Path::new(&location("new")).exists());
+        assert_eq!(total_allocated(&mut system), 0);
+
+        let items = vec![(location("small"), 20)];
+        let msg = create("small", Resources::Locations(items), OpenMode::ReadWrite);
+        system.block_on(router.send(msg)).unwrap().unwrap();
+        assert_eq!(total_allocated(&mut system), 20);
+
+        // Loaded storages are checked once their size is known
+        let unlimited = StorageRouter::new().start();
+        let items = vec![(location("existing"), 90)];
+        let msg = create("saved", Resources::Locations(items), OpenMode::ReadOnly);
+        system.block_on(unlimited.send(msg)).unwrap().unwrap();
+        let msg = Save {
+            id: "saved".to_string(),
+            location: location("saved.map"),
+        };
+        system.block_on(unlimited.send(msg)).unwrap().unwrap();
+
+        let msg = Load {
+            id: "loaded".to_string(),
+            location: location("saved.map"),
+            relocation: None,
+            open_mode: OpenMode::ReadOnly,
+        };
+        match system.block_on(router.send(msg)).unwrap() {
+            Ok(_) => panic!("Storage should not have been loaded"),
+            Err(error) => match error.kind {
+                ErrorKind::StorageError(StorageErrorKind::QuotaExceeded(110, 100)) => (),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+        assert_eq!(total_allocated(&mut system), 20);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
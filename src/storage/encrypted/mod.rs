@@ -0,0 +1,23 @@
+pub mod resource;
+
+use self::resource::{EncryptedResource, Key};
+use storage::generic::options::CreateOptions;
+use storage::generic::GenericStorage;
+use storage::resource::{Resource, ResourcePtr};
+use storage::{Result, StorageId};
+
+impl<R, P> GenericStorage<EncryptedResource<R>, P>
+where
+    R: Resource,
+    P: ResourcePtr<Target = EncryptedResource<R>>,
+{
+    /// Create a storage encrypted with `key`. Saved storages are loaded
+    /// with the key in their `LoadOptions`.
+    pub fn new_encrypted(name: StorageId, items: Vec<(String, usize)>, key: &Key) -> Result<Self> {
+        let options = CreateOptions {
+            resource: Some(key.clone()),
+            ..CreateOptions::default()
+        };
+        Self::with_options(name, items, &options)
+    }
+}
@@ -0,0 +1,465 @@
+use std::fmt;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crypto::aes::{self, KeySize};
+use serde::{Serialize, Serializer};
+
+use storage::error::ErrorKind;
+use storage::resource::{seek_position, OpenMode, PositionalResource, Resource};
+use storage::space::Filesystem;
+use storage::{Result, Size};
+
+pub const KEY_SIZE: usize = 32;
+/// Size of the per-resource nonce stored at the beginning of each resource
+pub const NONCE_SIZE: usize = 16;
+
+const BLOCK_SIZE: usize = 16;
+
+/// AES-256 key. Never serialized; passed to storages as resource options
+/// when they are created or loaded.
+#[derive(Clone, PartialEq)]
+pub struct Key([u8; KEY_SIZE]);
+
+impl Key {
+    pub fn new(bytes: [u8; KEY_SIZE]) -> Self {
+        Key(bytes)
+    }
+
+    pub fn random() -> Self {
+        Key(rand::random())
+    }
+
+    fn required(key: &Option<Key>) -> Result<Self> {
+        match key {
+            Some(key) => Ok(key.clone()),
+            None => err_new!(ErrorKind::KeyError(
+                "Encryption key not set in resource options".to_string()
+            )),
+        }
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Key(..)")
+    }
+}
+
+/// XOR `data` with the AES-CTR keystream starting at byte `offset`.
+/// The counter block for `offset` is the nonce incremented by the number of
+/// whole blocks preceding it, so any range can be processed independently.
+fn apply_keystream(key: &Key, nonce: &[u8; NONCE_SIZE], offset: usize, data: &mut [u8]) {
+    let block = (offset / BLOCK_SIZE) as u128;
+    let counter = u128::from_be_bytes(*nonce).wrapping_add(block);
+    let mut cipher = aes::ctr(KeySize::KeySize256, &key.0, &counter.to_be_bytes());
+
+    let skip = offset % BLOCK_SIZE;
+    if skip > 0 {
+        let zeros = [0u8; BLOCK_SIZE];
+        cipher.process(&zeros[..skip], &mut [0u8; BLOCK_SIZE][..skip]);
+    }
+
+    let input = data.to_vec();
+    cipher.process(&input, data);
+}
+
+/// Handle translating plaintext positions to the inner resource, past the
+/// nonce, and encrypting data on the fly
+pub struct EncryptedHandle<R> {
+    inner: R,
+    key: Key,
+    nonce: [u8; NONCE_SIZE],
+    position: u64,
+}
+
+impl<R> EncryptedHandle<R>
+where
+    R: Resource,
+{
+    fn seek_inner(&mut self) -> io::Result<()> {
+        let offset = self.position + NONCE_SIZE as u64;
+        self.inner.handle().seek(SeekFrom::Start(offset))?;
+        Ok(())
+    }
+}
+
+impl<R> fmt::Debug for EncryptedHandle<R>
+where
+    R: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EncryptedHandle")
+            .field("inner", &self.inner)
+            .field("position", &self.position)
+            .finish()
+    }
+}
+
+impl<R> Read for EncryptedHandle<R>
+where
+    R: Resource,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.seek_inner()?;
+        let read = self.inner.handle().read(buf)?;
+
+        apply_keystream(
+            &self.key,
+            &self.nonce,
+            self.position as usize,
+            &mut buf[..read],
+        );
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R> Write for EncryptedHandle<R>
+where
+    R: Resource,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut encrypted = buf.to_vec();
+        apply_keystream(
+            &self.key,
+            &self.nonce,
+            self.position as usize,
+            &mut encrypted,
+        );
+
+        self.seek_inner()?;
+        let written = self.inner.handle().write(&encrypted)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.handle().flush()
+    }
+}
+
+impl<R> Seek for EncryptedHandle<R>
+where
+    R: Resource,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => seek_position(self.position, delta),
+            SeekFrom::End(delta) => {
+                seek_position(self.inner.size().saturating_sub(NONCE_SIZE) as u64, delta)
+            }
+        };
+
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(io::Error::from(io::ErrorKind::InvalidInput)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EncryptedMetadata {
+    size: usize,
+}
+
+impl Size for EncryptedMetadata {
+    fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// Resource encrypted at rest with AES-256 in CTR mode. Each resource starts
+/// with a random nonce, followed by the encrypted content.
+///
+/// Keys are given as resource options when the resource is created or
+/// opened, so they never end up in a serialized storage.
+#[derive(Debug)]
+pub struct EncryptedResource<R> {
+    handle: EncryptedHandle<R>,
+}
+
+impl<R> EncryptedResource<R>
+where
+    R: Resource,
+{
+    fn new(inner: R, key: Key, nonce: [u8; NONCE_SIZE]) -> Self {
+        let handle = EncryptedHandle {
+            inner,
+            key,
+            nonce,
+            position: 0,
+        };
+        EncryptedResource { handle }
+    }
+
+    fn read_nonce(inner: &mut R) -> Result<[u8; NONCE_SIZE]> {
+        let mut nonce = [0u8; NONCE_SIZE];
+        let handle = inner.handle();
+        handle.seek(SeekFrom::Start(0))?;
+        handle.read_exact(&mut nonce)?;
+        Ok(nonce)
+    }
+
+    fn write_nonce(inner: &mut R) -> Result<[u8; NONCE_SIZE]> {
+        let nonce: [u8; NONCE_SIZE] = rand::random();
+        let handle = inner.handle();
+        handle.seek(SeekFrom::Start(0))?;
+        handle.write_all(&nonce)?;
+        Ok(nonce)
+    }
+}
+
+impl<R> Resource for EncryptedResource<R>
+where
+    R: Resource,
+{
+    type Handle = EncryptedHandle<R>;
+    type Metadata = EncryptedMetadata;
+    type Options = Option<Key>;
+
+    fn open(location: &String) -> Result<Self> {
+        Self::open_with(location, OpenMode::ReadWrite, &None)
+    }
+
+    fn open_with(location: &String, mode: OpenMode, key: &Option<Key>) -> Result<Self> {
+        let key = Key::required(key)?;
+        let mut inner = R::open_with(location, mode, &R::Options::default())?;
+        let nonce = Self::read_nonce(&mut inner)?;
+
+        Ok(EncryptedResource::new(inner, key, nonce))
+    }
+
+    fn create(location: &String, size: &usize) -> Result<Self> {
+        Self::create_with(location, size, &None)
+    }
+
+    /// Create the inner resource with room for a new nonce. Existing
+    /// resources are never adopted, as their content may be plaintext or
+    /// encrypted with a nonce that must not be reused; open them instead.
+    fn create_with(location: &String, size: &usize, key: &Option<Key>) -> Result<Self> {
+        let key = Key::required(key)?;
+        if R::exists(location) {
+            return err_new!(ErrorKind::AlreadyExists(location.clone()));
+        }
+
+        let mut inner = R::create_with(location, &(size + NONCE_SIZE), &R::Options::default())?;
+        let nonce = Self::write_nonce(&mut inner)?;
+
+        Ok(EncryptedResource::new(inner, key, nonce))
+    }
+
+    #[inline(always)]
+    fn exists(location: &String) -> bool {
+        R::exists(location)
+    }
+
+    fn metadata(location: &String) -> Result<Self::Metadata> {
+        let size = R::metadata(location)?.size().saturating_sub(NONCE_SIZE);
+        Ok(EncryptedMetadata { size })
+    }
+
+    #[inline(always)]
+    fn delete(location: &str) -> Result<()> {
+        R::delete(location)
+    }
+
+    #[inline(always)]
+    fn filesystem(location: &str) -> Result<Option<Filesystem>> {
+        R::filesystem(location)
+    }
+
+    #[inline(always)]
+    fn handle(&mut self) -> &mut Self::Handle {
+        &mut self.handle
+    }
+
+    #[inline(always)]
+    fn location(&self) -> String {
+        self.handle.inner.location()
+    }
+}
+
+impl<R> PositionalResource for EncryptedResource<R>
+where
+    R: PositionalResource,
+{
+    fn read_at(&self, offset: usize, into: &mut [u8]) -> io::Result<usize> {
+        let handle = &self.handle;
+        let read = handle.inner.read_at(offset + NONCE_SIZE, into)?;
+
+        apply_keystream(&handle.key, &handle.nonce, offset, &mut into[..read]);
+        Ok(read)
+    }
+
+    fn write_at(&self, offset: usize, from: &[u8]) -> io::Result<usize> {
+        let handle = &self.handle;
+        let mut encrypted = from.to_vec();
+
+        apply_keystream(&handle.key, &handle.nonce, offset, &mut encrypted);
+        handle.inner.write_at(offset + NONCE_SIZE, &encrypted)
+    }
+}
+
+impl<R> Clone for EncryptedResource<R>
+where
+    R: Resource,
+{
+    fn clone(&self) -> Self {
+        let handle = &self.handle;
+        EncryptedResource::new(handle.inner.clone(), handle.key.clone(), handle.nonce)
+    }
+}
+
+impl<R> Size for EncryptedResource<R>
+where
+    R: Resource,
+{
+    #[inline(always)]
+    fn size(&self) -> usize {
+        self.handle.inner.size().saturating_sub(NONCE_SIZE)
+    }
+}
+
+impl<R> Serialize for EncryptedResource<R>
+where
+    R: Resource,
+{
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.location().as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode;
+    use storage::generic::options::LoadOptions;
+    use storage::generic::resource::PositionalResourcePtr;
+    use storage::generic::{GenericStorage, SavedStorage};
+    use storage::memory::resource::MemoryResource;
+    use storage::Storage;
+
+    type EncryptedStorage = GenericStorage<EncryptedResource<MemoryResource>>;
+    type SharedEncryptedStorage = GenericStorage<
+        EncryptedResource<MemoryResource>,
+        PositionalResourcePtr<EncryptedResource<MemoryResource>>,
+    >;
+
+    fn items(prefix: &str, sizes: &[usize]) -> Vec<(String, usize)> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(i, size)| (format!("memory://{}/{}", prefix, i), *size))
+            .collect()
+    }
+
+    fn remove(items: &[(String, usize)]) {
+        items.iter().for_each(|(location, _)| {
+            MemoryResource::delete(location).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_keystream_offsets() {
+        let key = Key::random();
+        let nonce = [0xffu8; NONCE_SIZE];
+
+        let mut full = vec![0u8; 1000];
+        apply_keystream(&key, &nonce, 0, &mut full);
+
+        for (offset, len) in [(0, 1000), (1, 15), (15, 2), (16, 16), (333, 667)].iter() {
+            let mut part = vec![0u8; *len];
+            apply_keystream(&key, &nonce, *offset, &mut part);
+            assert_eq!(part[..], full[*offset..*offset + *len]);
+        }
+    }
+
+    #[test]
+    fn test_storage() {
+        let key = Key::random();
+        let items = items("test_encrypted_storage", &[100, 1000, 10]);
+        let data: Vec<u8> = (0..1110).map(|n| n as u8).collect();
+
+        let name = "encrypted".to_string();
+        let storage = EncryptedStorage::new_encrypted(name, items.clone(), &key).unwrap();
+        storage.write(0, &data).unwrap();
+
+        let mut read = vec![0u8; 600];
+        storage.read(50, &mut read).unwrap();
+        assert_eq!(read[..], data[50..650]);
+
+        let mut raw = vec![0u8; 1000];
+        let mut inner = MemoryResource::open(&items[1].0).unwrap();
+        inner
+            .handle()
+            .seek(SeekFrom::Start(NONCE_SIZE as u64))
+            .unwrap();
+        inner.handle().read_exact(&mut raw).unwrap();
+        assert_eq!(inner.size(), 1000 + NONCE_SIZE);
+        assert_ne!(raw[..], data[100..1100]);
+
+        let serialized = bincode::serialize(&storage).unwrap();
+        let load = |key: &Key| -> SharedEncryptedStorage {
+            let options = LoadOptions {
+                resource: Some(key.clone()),
+                ..LoadOptions::default()
+            };
+            let saved: SavedStorage = bincode::deserialize(&serialized).unwrap();
+            saved.open(&options).unwrap()
+        };
+
+        let mut read = vec![0u8; data.len()];
+        load(&key).read(0, &mut read).unwrap();
+        assert_eq!(read, data);
+
+        load(&Key::random()).read(0, &mut read).unwrap();
+        assert_ne!(read, data);
+
+        remove(&items);
+    }
+
+    #[test]
+    fn test_missing_key() {
+        let items = items("test_encrypted_missing_key", &[100]);
+
+        match EncryptedStorage::new("encrypted".to_string(), items.clone()) {
+            Ok(_) => panic!("Storage should not have been created without a key"),
+            Err(error) => match error.kind {
+                ErrorKind::KeyError(_) => (),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+
+        let _ = MemoryResource::delete(&items[0].0);
+    }
+
+    #[test]
+    fn test_create_existing() {
+        let key = Key::random();
+        let location = "memory://test_encrypted_create_existing".to_string();
+
+        let mut plain = MemoryResource::create(&location, &100).unwrap();
+        plain.handle().write_all(&[7u8; 100]).unwrap();
+
+        let result = EncryptedResource::<MemoryResource>::create_with(&location, &84, &Some(key));
+        match result.unwrap_err().kind {
+            ErrorKind::AlreadyExists(ref existing) => assert_eq!(existing, &location),
+            kind => panic!("Invalid error kind: {:?}", kind),
+        }
+
+        let mut read = [0u8; 100];
+        plain.handle().seek(SeekFrom::Start(0)).unwrap();
+        plain.handle().read_exact(&mut read).unwrap();
+        assert_eq!(read[..], [7u8; 100][..]);
+
+        MemoryResource::delete(&location).unwrap();
+    }
+}
@@ -10,6 +10,21 @@ pub enum ErrorKind {
     LocationError(String),
     MemoryError(String),
     IoError(String),
+    KeyError(String),
+    ReadOnly,
+    /// Resource at the location is locked by another storage or process
+    Locked(String),
+    /// Resource at the location exists and can't be created
+    AlreadyExists(String),
+    /// Bytes required by resources, existing ones included, and the quota
+    /// they exceed
+    QuotaExceeded(usize, usize),
+    /// Bytes required by new resources and available on their filesystem
+    InsufficientSpace(usize, usize),
+    /// Resources missing or with a size other than the recorded one,
+    /// given as `(location, recorded size, actual size)`. Storages saved
+    /// before sizes were recorded give a recorded size of 0.
+    InvalidResources(Vec<(String, usize, Option<usize>)>),
     Custom(String),
 }
 
@@ -24,6 +39,13 @@ impl Clone for ErrorKind {
             ErrorKind::LocationError(s) => ErrorKind::LocationError(s.clone()),
             ErrorKind::MemoryError(s) => ErrorKind::MemoryError(s.clone()),
             ErrorKind::IoError(error) => ErrorKind::IoError(format!("{:?}", error)),
+            ErrorKind::KeyError(s) => ErrorKind::KeyError(s.clone()),
+            ErrorKind::ReadOnly => ErrorKind::ReadOnly,
+            ErrorKind::Locked(s) => ErrorKind::Locked(s.clone()),
+            ErrorKind::AlreadyExists(s) => ErrorKind::AlreadyExists(s.clone()),
+            ErrorKind::QuotaExceeded(r, q) => ErrorKind::QuotaExceeded(*r, *q),
+            ErrorKind::InsufficientSpace(r, a) => ErrorKind::InsufficientSpace(*r, *a),
+            ErrorKind::InvalidResources(v) => ErrorKind::InvalidResources(v.clone()),
             ErrorKind::Custom(s) => ErrorKind::Custom(s.clone()),
         }
     }
@@ -0,0 +1,262 @@
+use std::fs::{create_dir_all, File};
+use std::io;
+use std::os::unix::fs::FileExt as UnixFileExt;
+use std::path::Path;
+use std::sync::Arc;
+
+use fs2::FileExt;
+use memmap::{Mmap, MmapOptions};
+
+use super::resource::FileResource;
+use storage::resource::{OpenMode, PositionalResource, Resource};
+use storage::space::Filesystem;
+use storage::{Result, Size};
+
+/// File resource mapped into memory when opened read-only. Reads are then
+/// served from the mapping and its content can be borrowed without copying.
+/// Read-write resources are never mapped and access the file directly, so
+/// borrowed content can't change through `write_at`.
+///
+/// The mapping reflects changes made to the file by other processes;
+/// such files must not be modified while the resource is in use.
+#[derive(Debug)]
+pub struct MmapResource {
+    file_handle: File,
+    file_location: String,
+    file_mode: OpenMode,
+    file_size: usize,
+    file_mmap: Option<Arc<Mmap>>,
+}
+
+impl MmapResource {
+    fn try_from(handle: File, location: &str, mode: OpenMode) -> Result<Self> {
+        let size = handle.metadata()?.len() as usize;
+        let mmap = match (mode, size) {
+            (OpenMode::ReadWrite, _) | (_, 0) => None,
+            (OpenMode::ReadOnly, _) => Some(Arc::new(unsafe {
+                MmapOptions::new().len(size).map(&handle)?
+            })),
+        };
+
+        Ok(MmapResource {
+            file_handle: handle,
+            file_location: location.to_string(),
+            file_mode: mode,
+            file_size: size,
+            file_mmap: mmap,
+        })
+    }
+
+    #[inline(always)]
+    fn data(&self) -> &[u8] {
+        match self.file_mmap {
+            Some(ref mmap) => &mmap[..],
+            None => &[],
+        }
+    }
+}
+
+impl Resource for MmapResource {
+    type Handle = File;
+    type Metadata = <FileResource as Resource>::Metadata;
+    type Options = ();
+
+    fn open(location: &String) -> Result<Self> {
+        Self::open_with(location, OpenMode::ReadWrite, &())
+    }
+
+    fn open_with(location: &String, mode: OpenMode, _options: &()) -> Result<Self> {
+        let handle = FileResource::open(location, false, mode)?;
+        MmapResource::try_from(handle, location, mode)
+    }
+
+    fn create(location: &String, size: &usize) -> Result<Self> {
+        if let Some(parent) = Path::new(location).parent() {
+            create_dir_all(parent)?;
+        }
+
+        let file = FileResource::open(location, true, OpenMode::ReadWrite)?;
+        if *size > 0 {
+            file.allocate(*size as u64)?;
+        }
+
+        MmapResource::try_from(file, location, OpenMode::ReadWrite)
+    }
+
+    #[inline(always)]
+    fn exists(location: &String) -> bool {
+        <FileResource as Resource>::exists(location)
+    }
+
+    #[inline(always)]
+    fn metadata(location: &String) -> Result<Self::Metadata> {
+        <FileResource as Resource>::metadata(location)
+    }
+
+    #[inline(always)]
+    fn delete(location: &str) -> Result<()> {
+        <FileResource as Resource>::delete(location)
+    }
+
+    #[inline(always)]
+    fn filesystem(location: &str) -> Result<Option<Filesystem>> {
+        <FileResource as Resource>::filesystem(location)
+    }
+
+    #[inline(always)]
+    fn handle(&mut self) -> &mut Self::Handle {
+        &mut self.file_handle
+    }
+
+    #[inline(always)]
+    fn location(&self) -> String {
+        self.file_location.clone()
+    }
+
+    #[inline(always)]
+    fn as_slice(&self) -> Option<&[u8]> {
+        match self.file_mode {
+            OpenMode::ReadOnly => Some(self.data()),
+            OpenMode::ReadWrite => None,
+        }
+    }
+}
+
+impl PositionalResource for MmapResource {
+    fn read_at(&self, offset: usize, into: &mut [u8]) -> io::Result<usize> {
+        if self.file_mode == OpenMode::ReadWrite {
+            return UnixFileExt::read_at(&self.file_handle, into, offset as u64);
+        }
+
+        let data = self.data();
+        if offset >= data.len() {
+            return Ok(0);
+        }
+
+        let len = std::cmp::min(into.len(), data.len() - offset);
+        into[..len].copy_from_slice(&data[offset..offset + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: usize, from: &[u8]) -> io::Result<usize> {
+        if self.file_mode == OpenMode::ReadOnly {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "mapped resource is read-only",
+            ));
+        }
+        UnixFileExt::write_at(&self.file_handle, from, offset as u64)
+    }
+}
+
+impl Clone for MmapResource {
+    fn clone(&self) -> Self {
+        MmapResource {
+            file_handle: self.file_handle.try_clone().unwrap(),
+            file_location: self.file_location.clone(),
+            file_mode: self.file_mode,
+            file_size: self.file_size,
+            file_mmap: self.file_mmap.clone(),
+        }
+    }
+}
+
+impl Size for MmapResource {
+    #[inline(always)]
+    fn size(&self) -> usize {
+        self.file_size
+    }
+}
+
+impl_resource_serde!(MmapResource);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use merkle_tree::digest::sha512::Sha512;
+    use merkle_tree::digest::Digest;
+    use merkle_tree::mode::Mode;
+    use storage::generic::resource::PositionalResourcePtr;
+    use storage::generic::GenericStorage;
+    use storage::map::chunk::Layout;
+    use storage::map::StorageMap;
+    use storage::Storage;
+
+    type MmapStorage = GenericStorage<MmapResource, PositionalResourcePtr<MmapResource>>;
+
+    fn items(name: &str, sizes: &[usize]) -> Vec<(String, usize)> {
+        let dir = std::env::temp_dir().join(format!("golem-mmap-{}-{}", name, std::process::id()));
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(i, size)| (dir.join(i.to_string()).display().to_string(), *size))
+            .collect()
+    }
+
+    fn cleanup(items: &[(String, usize)]) {
+        if let Some((location, _)) = items.first() {
+            let _ = std::fs::remove_dir_all(Path::new(location).parent().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_read_slice() {
+        let items = items("read_slice", &[1000, 3000]);
+        let storage = MmapStorage::new("mmap".to_string(), items.clone()).unwrap();
+
+        let data: Vec<u8> = (0..4000).map(|n| (n % 251) as u8).collect();
+        storage.write(0, &data).unwrap();
+
+        let mut read = vec![0u8; data.len()];
+        storage.read(0, &mut read).unwrap();
+        assert_eq!(read, data);
+        assert_eq!(storage.read_slice(0, 1000).unwrap(), None);
+        drop(storage);
+
+        let name = "mmap".to_string();
+        let storage = MmapStorage::with_mode(name, items.clone(), OpenMode::ReadOnly).unwrap();
+
+        storage.read(0, &mut read).unwrap();
+        assert_eq!(read, data);
+
+        assert_eq!(storage.read_slice(0, 1000).unwrap(), Some(&data[..1000]));
+        assert_eq!(storage.read_slice(1500, 2500).unwrap(), Some(&data[1500..]));
+        assert_eq!(storage.read_slice(500, 1000).unwrap(), None);
+        assert!(storage.read_slice(3000, 1001).is_err());
+
+        cleanup(&items);
+    }
+
+    #[test]
+    fn test_storage_map() {
+        let items = items("storage_map", &[20000, 12000, 500]);
+        let data: Vec<u8> = (0..32500).map(|n| (n % 253) as u8).collect();
+        {
+            let storage = MmapStorage::new("mmap".to_string(), items.clone()).unwrap();
+            storage.write(0, &data).unwrap();
+        }
+
+        let layout = Layout::fixed(16384);
+        let name = "mmap".to_string();
+        let map = StorageMap::<MmapStorage>::new_with_mode(
+            name,
+            items.clone(),
+            &layout,
+            Mode::Rfc6962,
+            OpenMode::ReadOnly,
+        )
+        .unwrap();
+        assert_eq!(
+            map.storage().read_slice(0, 16384).unwrap(),
+            Some(&data[..16384])
+        );
+
+        let hashes: Vec<_> = data
+            .chunks(16384)
+            .map(|piece| Mode::Rfc6962.hash_leaf(&mut Sha512::new(), piece))
+            .collect();
+        assert_eq!(map.hashes(), hashes);
+
+        cleanup(&items);
+    }
+}
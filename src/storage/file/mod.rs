@@ -1 +1,3 @@
+#[cfg(unix)]
+pub mod mmap;
 pub mod resource;
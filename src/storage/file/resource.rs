@@ -1,10 +1,17 @@
-use std::fs::{create_dir_all, File, Metadata, OpenOptions};
+use std::fs::{create_dir_all, remove_file, File, Metadata, OpenOptions};
+#[cfg(unix)]
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::FileExt as UnixFileExt;
 use std::path::Path;
 
-use fs2::FileExt;
+use fs2::{self, FileExt};
 
 use storage::error::{Error, ErrorKind};
-use storage::resource::Resource;
+#[cfg(unix)]
+use storage::resource::PositionalResource;
+use storage::resource::{OpenMode, Resource};
+use storage::space::Filesystem;
 use storage::{Result, Size};
 
 impl Size for Metadata {
@@ -36,31 +43,54 @@ impl FileResource {
     }
 
     fn try_from(handle: File, location: &String) -> Result<Self> {
-        let size = handle.allocated_size()? as usize;
+        let size = handle.metadata()?.len() as usize;
 
         Ok(FileResource::new(handle, location, size))
     }
 
-    fn open(location: &String, create: bool) -> Result<<Self as Resource>::Handle> {
+    /// Open the file and take an advisory lock on it, shared for read-only
+    /// and exclusive for read-write handles. The lock is released once all
+    /// handles are closed.
+    pub(super) fn open(
+        location: &String,
+        create: bool,
+        mode: OpenMode,
+    ) -> Result<<Self as Resource>::Handle> {
         let path = Path::new(location);
         let file = OpenOptions::new()
             .create(create)
             .read(true)
-            .write(true)
+            .write(mode == OpenMode::ReadWrite)
             .append(false)
             .truncate(false)
             .open(path)?;
 
-        Ok(file)
+        let locked = match mode {
+            OpenMode::ReadWrite => FileExt::try_lock_exclusive(&file),
+            OpenMode::ReadOnly => FileExt::try_lock_shared(&file),
+        };
+
+        match locked {
+            Ok(()) => Ok(file),
+            Err(ref e) if e.raw_os_error() == fs2::lock_contended_error().raw_os_error() => {
+                err_new!(ErrorKind::Locked(location.clone()))
+            }
+            Err(e) => Err(e.into()),
+        }
     }
 }
 
 impl Resource for FileResource {
     type Handle = File;
     type Metadata = Metadata;
+    type Options = ();
 
     fn open(location: &String) -> Result<Self> {
-        let handle = FileResource::open(location, false)?;
+        Self::open_with(location, OpenMode::ReadWrite, &())
+    }
+
+    fn open_with(location: &String, mode: OpenMode, _options: &()) -> Result<Self> {
+        let handle = FileResource::open(location, false, mode)?;
         FileResource::try_from(handle, location)
     }
 
@@ -69,8 +99,10 @@ impl Resource for FileResource {
             create_dir_all(parent)?;
         }
 
-        let file = FileResource::open(location, true)?;
-        file.allocate(*size as u64)?;
+        let file = FileResource::open(location, true, OpenMode::ReadWrite)?;
+        if *size > 0 {
+            file.allocate(*size as u64)?;
+        }
 
         FileResource::try_from(file, location)
     }
@@ -86,6 +118,15 @@ impl Resource for FileResource {
         Ok(result)
     }
 
+    fn delete(location: &str) -> Result<()> {
+        remove_file(location)?;
+        Ok(())
+    }
+
+    fn filesystem(location: &str) -> Result<Option<Filesystem>> {
+        Filesystem::of(location).map(Some)
+    }
+
     #[inline(always)]
     fn handle(&mut self) -> &mut Self::Handle {
         &mut self.file_handle
@@ -97,6 +138,19 @@ impl Resource for FileResource {
     }
 }
 
+#[cfg(unix)]
+impl PositionalResource for FileResource {
+    #[inline(always)]
+    fn read_at(&self, offset: usize, into: &mut [u8]) -> io::Result<usize> {
+        UnixFileExt::read_at(&self.file_handle, into, offset as u64)
+    }
+
+    #[inline(always)]
+    fn write_at(&self, offset: usize, from: &[u8]) -> io::Result<usize> {
+        UnixFileExt::write_at(&self.file_handle, from, offset as u64)
+    }
+}
+
 impl Clone for FileResource {
     fn clone(&self) -> Self {
         let handle = self.file_handle.try_clone().unwrap();
@@ -1,39 +1,63 @@
+pub mod options;
 #[macro_use]
 pub mod resource;
 
-use std::fmt;
-use std::io::{Read, Write};
+use std::collections::HashMap;
 use std::marker::PhantomData;
-use std::ops::DerefMut;
+use std::path::{Path, PathBuf};
 
 use indexmap::IndexMap;
-use serde::de;
 use serde::ser::SerializeSeq;
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::{Deserialize, Serialize, Serializer};
 
+use self::options::{CreateOptions, LoadOptions};
 use self::resource::GenericResourcePtr;
 use storage::error::ErrorKind;
-use storage::resource::{Resource, ResourcePtr};
+use storage::resource::{OpenMode, Resource, ResourcePtr};
 use storage::shard::{Shard, ShardReader, ShardWriter, Sharded};
+use storage::space::Quota;
+use storage::tree::{self, DirTree};
 use storage::view::uniform::UniformView;
 use storage::view::{View, ViewVec};
 use storage::{Result, Size, Storage, StorageId};
 
-#[derive(Serialize, Deserialize)]
-pub struct GenericStorage<R>
+/// Storage of resources, saved as their keys and sizes. Saved storages are
+/// deserialized as a `SavedStorage` and opened with `SavedStorage::open`.
+#[derive(Serialize)]
+#[serde(bound = "")]
+pub struct GenericStorage<R, P = GenericResourcePtr<R>>
 where
     R: Resource,
+    P: ResourcePtr<Target = R>,
 {
     pub name: StorageId,
+    /// Root directory of storages created from a directory tree, which
+    /// resource keys are relative to
+    root: Option<String>,
+    tree: Option<DirTree>,
     #[serde(serialize_with = "serialize_resources")]
-    #[serde(deserialize_with = "deserialize_resources")]
-    resources: IndexMap<StorageId, <GenericStorage<R> as Storage>::Ptr>,
+    resources: IndexMap<StorageId, P>,
     total_size: usize,
+    #[serde(skip)]
+    mode: OpenMode,
+    #[serde(skip)]
+    phantom: PhantomData<R>,
 }
 
-impl<R> GenericStorage<R>
+/// Serialized form of a `GenericStorage`, before its resources are opened
+#[derive(Deserialize)]
+pub struct SavedStorage {
+    name: StorageId,
+    root: Option<String>,
+    tree: Option<DirTree>,
+    resources: Vec<(StorageId, usize)>,
+    total_size: usize,
+}
+
+impl<R, P> GenericStorage<R, P>
 where
     R: Resource,
+    P: ResourcePtr<Target = R>,
 {
     pub fn collect<S, I>(items: I) -> Result<Vec<(String, usize)>>
     where
@@ -51,11 +75,206 @@ where
         Ok(results)
     }
 
-    fn add(&mut self, location: &String, size: &usize) -> Result<()> {
-        let resource = if R::exists(location) {
-            R::open(location)?
+    /// Create a storage of existing or new resources with `options`
+    pub fn with_options(
+        name: StorageId,
+        items: Vec<(String, usize)>,
+        options: &CreateOptions<R::Options>,
+    ) -> Result<Self> {
+        let mut storage = Self::empty(name);
+        storage.mode = options.mode;
+        storage.add_all(&items, options)?;
+        Ok(storage)
+    }
+
+    /// Create a storage from the directory tree at `root`
+    pub fn from_dir(
+        name: StorageId,
+        root: &str,
+        options: &CreateOptions<R::Options>,
+    ) -> Result<Self> {
+        Self::from_tree(name, root, DirTree::walk(root)?, options)
+    }
+
+    /// Create a storage of files in `tree`, relative to a destination
+    /// `root`. Missing directories and files are created, unless read-only.
+    pub fn from_tree(
+        name: StorageId,
+        root: &str,
+        tree: DirTree,
+        options: &CreateOptions<R::Options>,
+    ) -> Result<Self> {
+        tree.validate()?;
+        let items = tree.items();
+
+        let mut storage = Self::empty(name);
+        storage.root = Some(root.to_string());
+        storage.tree = Some(tree);
+        storage.mode = options.mode;
+        storage.add_all(&items, options)?;
+        Ok(storage)
+    }
+
+    #[inline]
+    pub fn mode(&self) -> OpenMode {
+        self.mode
+    }
+
+    /// Bytes allocated on disk for the open resources of this storage,
+    /// whether it created them or they existed before
+    #[inline]
+    pub fn allocated(&self) -> usize {
+        self.total_size
+    }
+
+    #[inline]
+    pub fn root(&self) -> Option<&String> {
+        self.root.as_ref()
+    }
+
+    #[inline]
+    pub fn tree(&self) -> Option<&DirTree> {
+        self.tree.as_ref()
+    }
+
+    /// Root of a directory tree storage, or locations of all resources
+    pub fn locations(&self) -> Vec<String> {
+        match self.root {
+            Some(ref root) => vec![root.clone()],
+            None => self.resources.keys().cloned().collect(),
+        }
+    }
+
+    /// Apply file and directory modes recorded in the tree, e.g. once all
+    /// data has been written
+    pub fn apply_modes(&self) -> Result<()> {
+        match (&self.root, &self.tree) {
+            (Some(root), Some(tree)) => tree.apply_modes(root),
+            _ => Ok(()),
+        }
+    }
+
+    fn empty(name: StorageId) -> Self {
+        GenericStorage {
+            name,
+            root: None,
+            tree: None,
+            resources: IndexMap::new(),
+            total_size: 0,
+            mode: OpenMode::default(),
+            phantom: PhantomData,
+        }
+    }
+
+    fn location(&self, key: &str) -> String {
+        match self.root {
+            Some(ref root) => tree::join(root, key),
+            None => key.to_string(),
+        }
+    }
+
+    /// Add resources, creating missing ones and tree directories once they
+    /// are known to fit the quota and the space available on their
+    /// filesystems. Resources and directories created before a failure are
+    /// removed.
+    fn add_all(
+        &mut self,
+        items: &[(String, usize)],
+        options: &CreateOptions<R::Options>,
+    ) -> Result<()> {
+        self.preflight(items, options.quota)?;
+
+        let mut created = Vec::new();
+        let mut dirs = Vec::new();
+        let result = self.create_dirs(&mut dirs).and_then(|_| {
+            items.iter().try_for_each(|(key, size)| {
+                self.total_size += size;
+                self.add(key, size, &options.resource, &mut created, &mut dirs)
+            })
+        });
+
+        if result.is_err() {
+            // Close handles before removing their resources
+            self.resources.clear();
+            created.iter().for_each(|location| {
+                let _ = R::delete(location);
+            });
+            tree::remove_dirs(&dirs);
+        }
+        result
+    }
+
+    /// Create the directories of the tree, unless read-only
+    fn create_dirs(&self, dirs: &mut Vec<PathBuf>) -> Result<()> {
+        match (&self.root, &self.tree) {
+            (Some(root), Some(tree)) if self.mode == OpenMode::ReadWrite => {
+                tree.create_dirs(root, dirs)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that all resources, existing ones included, fit the quota, as
+    /// `allocated` counts them, and that new ones fit the space available on
+    /// their filesystems
+    fn preflight(&self, items: &[(String, usize)], quota: Option<Quota>) -> Result<()> {
+        if let Some(Quota(quota)) = quota {
+            let required = items.iter().map(|(_, size)| size).sum();
+            if required > quota {
+                return err_new!(ErrorKind::QuotaExceeded(required, quota));
+            }
+        }
+
+        if self.mode == OpenMode::ReadOnly {
+            return Ok(());
+        }
+
+        let mut filesystems = HashMap::new();
+        for (key, size) in items.iter() {
+            let location = self.location(key);
+            if R::exists(&location) {
+                continue;
+            }
+
+            if let Some(filesystem) = R::filesystem(&location)? {
+                let (fs_required, _) = filesystems
+                    .entry(filesystem.id)
+                    .or_insert((0, filesystem.available.min(usize::MAX as u64) as usize));
+                *fs_required += size;
+            }
+        }
+
+        for (required, available) in filesystems.values() {
+            if required > available {
+                return err_new!(ErrorKind::InsufficientSpace(*required, *available));
+            }
+        }
+        Ok(())
+    }
+
+    fn add(
+        &mut self,
+        key: &str,
+        size: &usize,
+        options: &R::Options,
+        created: &mut Vec<String>,
+        dirs: &mut Vec<PathBuf>,
+    ) -> Result<()> {
+        let location = self.location(key);
+        let resource = if R::exists(&location) || self.mode == OpenMode::ReadOnly {
+            R::open_with(&location, self.mode, options)?
         } else {
-            R::create(location, size)?
+            // Resources may create their missing parent directories
+            let parents = match Path::new(&location).parent() {
+                Some(parent) => tree::missing_dirs(parent),
+                None => Vec::new(),
+            };
+            let result = R::create_with(&location, size, options);
+            dirs.extend(parents.into_iter().filter(|dir| dir.is_dir()));
+
+            let resource = result?;
+            created.push(location);
+            resource
         };
 
         if resource.size() != *size {
@@ -63,14 +282,125 @@ where
         }
 
         self.resources
-            .insert(location.clone(), ResourcePtr::new(resource));
+            .insert(key.to_string(), ResourcePtr::new(resource));
         Ok(())
     }
 }
 
-impl<R> Size for GenericStorage<R>
+/// Serialized form of a `GenericStorage` saved before resource sizes and
+/// directory trees were recorded
+#[derive(Deserialize)]
+pub struct LegacySavedStorage {
+    name: StorageId,
+    resources: Vec<StorageId>,
+    total_size: usize,
+}
+
+impl LegacySavedStorage {
+    /// Open saved resources like `SavedStorage::open`, taking the sizes of
+    /// resources from the resources themselves. Fails with every missing
+    /// resource listed, with a recorded size of 0.
+    pub fn open<R, P>(self, options: &LoadOptions<R::Options>) -> Result<GenericStorage<R, P>>
+    where
+        R: Resource,
+        P: ResourcePtr<Target = R>,
+    {
+        let locations = match options.relocation {
+            Some(ref relocation) => relocation.relocate(&self.resources),
+            None => self.resources.clone(),
+        };
+
+        let missing: Vec<_> = locations
+            .iter()
+            .filter(|location| !R::exists(location))
+            .map(|location| (location.clone(), 0, None))
+            .collect();
+        if !missing.is_empty() {
+            return err_new!(ErrorKind::InvalidResources(missing));
+        }
+
+        let mut sizes = Vec::with_capacity(locations.len());
+        for location in locations.iter() {
+            sizes.push(R::metadata(location)?.size());
+        }
+
+        let size = sizes.iter().sum();
+        if size != self.total_size {
+            return err_new!(ErrorKind::SizeMismatch(size, self.total_size));
+        }
+
+        let saved = SavedStorage {
+            name: self.name,
+            root: None,
+            tree: None,
+            resources: self.resources.into_iter().zip(sizes).collect(),
+            total_size: self.total_size,
+        };
+        saved.open(options)
+    }
+}
+
+impl SavedStorage {
+    /// Open saved resources at locations mapped by the relocation in
+    /// `options`, if any. Fails with every missing or resized resource
+    /// listed, or on the first resource failing to open, e.g. locked ones.
+    pub fn open<R, P>(self, options: &LoadOptions<R::Options>) -> Result<GenericStorage<R, P>>
+    where
+        R: Resource,
+        P: ResourcePtr<Target = R>,
+    {
+        if let Some(ref tree) = self.tree {
+            tree.validate()?;
+        }
+
+        let (keys, sizes): (Vec<StorageId>, Vec<usize>) = self.resources.into_iter().unzip();
+
+        let mut storage = GenericStorage::empty(self.name);
+        storage.tree = self.tree;
+        storage.total_size = self.total_size;
+        storage.mode = options.mode;
+
+        let keys = match (self.root, &options.relocation) {
+            (Some(root), Some(relocation)) => {
+                storage.root = Some(relocation.relocate_root(&root));
+                keys
+            }
+            (Some(root), None) => {
+                storage.root = Some(root);
+                keys
+            }
+            (None, Some(relocation)) => relocation.relocate(&keys),
+            (None, None) => keys,
+        };
+
+        let mut invalid = Vec::new();
+
+        for (key, size) in keys.into_iter().zip(sizes) {
+            let location = storage.location(&key);
+            let resource = match R::exists(&location) {
+                true => Some(R::open_with(&location, storage.mode, &options.resource)?),
+                false => None,
+            };
+
+            match resource {
+                Some(resource) if resource.size() == size => {
+                    storage.resources.insert(key, ResourcePtr::new(resource));
+                }
+                resource => invalid.push((location, size, resource.map(|r| r.size()))),
+            }
+        }
+
+        if !invalid.is_empty() {
+            return err_new!(ErrorKind::InvalidResources(invalid));
+        }
+        Ok(storage)
+    }
+}
+
+impl<R, P> Size for GenericStorage<R, P>
 where
     R: Resource,
+    P: ResourcePtr<Target = R>,
 {
     #[inline(always)]
     fn size(&self) -> usize {
@@ -78,25 +408,23 @@ where
     }
 }
 
-impl<R> Storage for GenericStorage<R>
+impl<R, P> Storage for GenericStorage<R, P>
 where
     R: Resource,
+    P: ResourcePtr<Target = R>,
 {
-    type Ptr = GenericResourcePtr<R>;
+    type Ptr = P;
 
     fn new(name: StorageId, items: Vec<(String, usize)>) -> Result<Self> {
-        let mut storage = GenericStorage {
-            name,
-            resources: IndexMap::new(),
-            total_size: 0,
-        };
-
-        items.iter().try_for_each(|(location, size)| {
-            storage.total_size += size;
-            storage.add(location, size)
-        })?;
+        Self::with_options(name, items, &CreateOptions::default())
+    }
 
-        Ok(storage)
+    fn with_mode(name: StorageId, items: Vec<(String, usize)>, mode: OpenMode) -> Result<Self> {
+        let options = CreateOptions {
+            mode,
+            ..CreateOptions::default()
+        };
+        Self::with_options(name, items, &options)
     }
 
     fn read(&self, offset: usize, into: &mut [u8]) -> Result<usize> {
@@ -105,32 +433,30 @@ where
         let mut start: usize = 0;
         let mut end: usize;
 
-        for (mut resource, shard) in view {
-            let mut borrowed = resource.try_borrow_mut()?;
-            let resource = borrowed.deref_mut();
-
+        for (resource, shard) in view {
             end = start + shard.size();
             let slice = &mut into[start..end];
-            start += self.read_shard(resource, &shard, slice)?;
+            start += self.read_shard(&resource, &shard, slice)?;
         }
 
         Ok(start)
     }
 
     fn write(&self, offset: usize, from: &[u8]) -> Result<usize> {
+        if self.mode == OpenMode::ReadOnly {
+            return err_new!(ErrorKind::ReadOnly);
+        }
+
         let view = self.view(offset, from.len())?;
 
         let mut start: usize = 0;
         let mut end: usize;
         let mut slice: &[u8];
 
-        for (mut resource, shard) in view {
-            let mut borrowed = resource.try_borrow_mut()?;
-            let resource = borrowed.deref_mut();
-
+        for (resource, shard) in view {
             end = start + shard.size();
             slice = &from[start..end];
-            start += self.write_shard(resource, &shard, slice)?;
+            start += self.write_shard(&resource, &shard, slice)?;
         }
 
         Ok(start)
@@ -139,11 +465,43 @@ where
     fn name(&self) -> &StorageId {
         &self.name
     }
+
+    fn read_slice(&self, offset: usize, size: usize) -> Result<Option<&[u8]>> {
+        if offset + size > self.total_size {
+            return err_new!(ErrorKind::InvalidOffsetAndSize(offset, size));
+        }
+        // Writes through a shared reference would alias borrowed content
+        if self.mode != OpenMode::ReadOnly {
+            return Ok(None);
+        }
+        if size == 0 {
+            return Ok(Some(&[]));
+        }
+
+        let mut start: usize = 0;
+
+        for resource in self.resources.values() {
+            let end = start + resource.size();
+            if offset < end {
+                if offset + size > end {
+                    return Ok(None);
+                }
+                let slice = resource
+                    .as_slice()
+                    .map(|slice| &slice[offset - start..offset - start + size]);
+                return Ok(slice);
+            }
+            start = end;
+        }
+
+        Ok(None)
+    }
 }
 
-impl<R> Sharded for GenericStorage<R>
+impl<R, P> Sharded for GenericStorage<R, P>
 where
     R: Resource,
+    P: ResourcePtr<Target = R>,
 {
     fn view(&self, start_idx: usize, size: usize) -> Result<ViewVec<<Self as Storage>::Ptr>> {
         let mut builder = UniformView::<<Self as Storage>::Ptr>::new(start_idx, start_idx + size);
@@ -154,73 +512,41 @@ where
     }
 }
 
-impl<R> ShardReader for GenericStorage<R>
+impl<R, P> ShardReader for GenericStorage<R, P>
 where
     R: Resource,
+    P: ResourcePtr<Target = R>,
 {
     #[inline]
     fn read_shard(
         &self,
-        resource: &mut <<Self as Storage>::Ptr as ResourcePtr>::Target,
+        resource: &<Self as Storage>::Ptr,
         shard: &Shard,
         into: &mut [u8],
     ) -> Result<usize> {
-        self.seek(resource, shard)?;
-        let read = resource.handle().read(into)?;
-        Ok(read)
+        if into.len() != shard.size() {
+            return Err(shard.into());
+        }
+        resource.read_at(shard.start, into)
     }
 }
 
-impl<R> ShardWriter for GenericStorage<R>
+impl<R, P> ShardWriter for GenericStorage<R, P>
 where
     R: Resource,
+    P: ResourcePtr<Target = R>,
 {
     #[inline]
     fn write_shard(
         &self,
-        resource: &mut <<Self as Storage>::Ptr as ResourcePtr>::Target,
+        resource: &<Self as Storage>::Ptr,
         shard: &Shard,
         from: &[u8],
     ) -> Result<usize> {
-        self.seek(resource, shard)?;
-        let written = resource.handle().write(from)?;
-        Ok(written)
-    }
-}
-
-struct ResourceSeqVisitor<P> {
-    phantom: PhantomData<P>,
-}
-
-impl<'de, 'a, P: 'a> de::Visitor<'de> for ResourceSeqVisitor<P>
-where
-    P: ResourcePtr,
-{
-    type Value = IndexMap<StorageId, P>;
-
-    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        write!(formatter, "a sequence of strings")
-    }
-
-    fn visit_seq<A>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error>
-    where
-        A: de::SeqAccess<'de>,
-    {
-        let mut map = Self::Value::new();
-
-        while let Some(elem) = seq.next_element()? {
-            match P::Target::open(&elem) {
-                Ok(res) => {
-                    let ptr = P::new(res);
-                    map.insert(elem, ptr);
-                }
-                Err(err) => {
-                    return Err(de::Error::custom(err));
-                }
-            }
+        if from.len() != shard.size() {
+            return Err(shard.into());
         }
-
-        Ok(map)
+        resource.write_at(shard.start, from)
     }
 }
 
@@ -233,32 +559,26 @@ where
     S: Serializer,
 {
     let mut seq = serializer.serialize_seq(Some(map.len()))?;
-    for key in map.keys() {
-        seq.serialize_element(key)?;
+    for (key, resource) in map.iter() {
+        seq.serialize_element(&(key, resource.size()))?;
     }
     seq.end()
 }
 
-fn deserialize_resources<'de, D, P>(
-    deserializer: D,
-) -> std::result::Result<IndexMap<StorageId, P>, D::Error>
-where
-    P: ResourcePtr,
-    D: Deserializer<'de>,
-{
-    let visitor = ResourceSeqVisitor {
-        phantom: PhantomData,
-    };
-    deserializer.deserialize_seq(visitor)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bincode;
+    use std::sync::Arc;
+    use std::thread;
+    use storage::generic::resource::SharedResourcePtr;
+    use storage::memory::resource::MemoryResource;
+    use storage::relocation::Relocation;
     use storage::tests::common::resource::TestResource;
     use streaming_iterator::StreamingIterator;
 
     type TestStorage = GenericStorage<TestResource>;
+    type SharedTestStorage = GenericStorage<TestResource, SharedResourcePtr<TestResource>>;
 
     fn make_size(n: i32) -> i32 {
         (n + 1) * 128
@@ -439,4 +759,307 @@ mod tests {
             assert_eq!(data[..], expected[..]);
         }
     }
+
+    #[test]
+    fn test_shared_read() {
+        let resources = resources_of_size(16, 1000);
+        let storage = SharedTestStorage::new("Test storage".to_string(), resources).unwrap();
+        let storage = Arc::new(storage);
+
+        let threads: Vec<_> = (0..8)
+            .map(|n| {
+                let storage = storage.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        let mut read = vec![0u8; 1000];
+                        storage.read(n * 1000, &mut read[..]).unwrap();
+                        assert_eq!(read[..], make_vec(1000)[..]);
+                    }
+                })
+            })
+            .collect();
+
+        threads.into_iter().for_each(|t| t.join().unwrap());
+    }
+
+    #[test]
+    fn test_relocated_load() {
+        type MemoryStorage = GenericStorage<MemoryResource>;
+
+        let items: Vec<(String, usize)> = (0..3)
+            .map(|n| (format!("relocated_old/{}", n), 100))
+            .collect();
+        let storage = MemoryStorage::new("relocated".to_string(), items.clone()).unwrap();
+        let serialized = bincode::serialize(&storage).unwrap();
+
+        let moved: Vec<String> = (0..3).map(|n| format!("relocated_new/{}", n)).collect();
+        MemoryResource::insert(&moved[0], vec![1u8; 100]).unwrap();
+        MemoryResource::insert(&moved[2], vec![2u8; 99]).unwrap();
+
+        let options = LoadOptions {
+            relocation: Some(Relocation::Rebase(vec![(
+                "relocated_old".to_string(),
+                "relocated_new".to_string(),
+            )])),
+            ..LoadOptions::default()
+        };
+        let load = || {
+            let saved: SavedStorage = bincode::deserialize(&serialized).unwrap();
+            saved.open::<MemoryResource, GenericResourcePtr<MemoryResource>>(&options)
+        };
+        match load() {
+            Ok(_) => panic!("Storage should not have been loaded"),
+            Err(error) => {
+                let message = format!("{:?}", error.kind);
+                assert!(message.contains(&moved[1]) && message.contains(&moved[2]));
+            }
+        }
+
+        MemoryResource::insert(&moved[1], vec![3u8; 100]).unwrap();
+        MemoryResource::insert(&moved[2], vec![2u8; 100]).unwrap();
+        let loaded = load().unwrap();
+
+        let mut read = vec![0u8; 300];
+        loaded.read(0, &mut read).unwrap();
+        assert_eq!(read[..100], [1u8; 100][..]);
+        assert_eq!(read[200..], [2u8; 100][..]);
+
+        items
+            .iter()
+            .map(|(location, _)| location)
+            .chain(moved.iter())
+            .for_each(|location| {
+                MemoryResource::delete(location).unwrap();
+            });
+    }
+
+    #[test]
+    fn test_invalid_resources() {
+        let saved = SavedStorage {
+            name: "invalid".to_string(),
+            root: None,
+            tree: None,
+            resources: vec![
+                ("invalid_resources/0".to_string(), 10),
+                ("invalid_resources/1".to_string(), 20),
+                ("invalid_resources/2".to_string(), 30),
+            ],
+            total_size: 60,
+        };
+        MemoryResource::insert(&saved.resources[1].0, vec![0u8; 20]).unwrap();
+        MemoryResource::insert(&saved.resources[2].0, vec![0u8; 31]).unwrap();
+
+        match saved
+            .open::<MemoryResource, GenericResourcePtr<MemoryResource>>(&LoadOptions::default())
+        {
+            Ok(_) => panic!("Storage should not have been loaded"),
+            Err(error) => match error.kind {
+                ErrorKind::InvalidResources(invalid) => assert_eq!(
+                    invalid,
+                    vec![
+                        ("invalid_resources/0".to_string(), 10, None),
+                        ("invalid_resources/2".to_string(), 30, Some(31)),
+                    ]
+                ),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+
+        MemoryResource::delete("invalid_resources/1").unwrap();
+        MemoryResource::delete("invalid_resources/2").unwrap();
+    }
+
+    #[test]
+    fn test_open_error() {
+        use std::fs;
+        use storage::file::resource::FileResource;
+
+        let dir = std::env::temp_dir().join(format!("golem-open-error-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let saved = SavedStorage {
+            name: "open_error".to_string(),
+            root: None,
+            tree: None,
+            resources: vec![
+                (dir.display().to_string(), 10),
+                (dir.join("missing").display().to_string(), 10),
+            ],
+            total_size: 20,
+        };
+
+        match saved.open::<FileResource, GenericResourcePtr<FileResource>>(&LoadOptions::default())
+        {
+            Ok(_) => panic!("Storage should not have been loaded"),
+            Err(error) => match error.kind {
+                ErrorKind::IoError(_) => (),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_read_only() {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+        use storage::file::resource::FileResource;
+
+        type FileStorage = GenericStorage<FileResource>;
+
+        let path = std::env::temp_dir().join(format!("golem-read-only-{}", std::process::id()));
+        let missing = format!("{}-missing", path.display());
+        let location = path.display().to_string();
+        let data = make_vec(1000);
+        fs::write(&path, &data).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o444)).unwrap();
+
+        let items = vec![(location.clone(), data.len())];
+        let storage = FileStorage::with_mode("ro".to_string(), items, OpenMode::ReadOnly).unwrap();
+        assert_eq!(storage.mode(), OpenMode::ReadOnly);
+
+        let mut read = vec![0u8; data.len()];
+        storage.read(0, &mut read).unwrap();
+        assert_eq!(read, data);
+
+        let assert_rejected = |storage: &FileStorage| match storage.write(0, &[0u8; 10]) {
+            Ok(_) => panic!("Read-only storage should not have been written"),
+            Err(error) => match error.kind {
+                ErrorKind::ReadOnly => (),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        };
+        assert_rejected(&storage);
+
+        let serialized = bincode::serialize(&storage).unwrap();
+        let options = LoadOptions {
+            mode: OpenMode::ReadOnly,
+            ..LoadOptions::default()
+        };
+        let saved: SavedStorage = bincode::deserialize(&serialized).unwrap();
+        let loaded: FileStorage = saved.open(&options).unwrap();
+        assert_rejected(&loaded);
+        assert_eq!(fs::read(&path).unwrap(), data);
+
+        let items = vec![(missing.clone(), 10)];
+        assert!(FileStorage::with_mode("ro".to_string(), items, OpenMode::ReadOnly).is_err());
+        assert!(!std::path::Path::new(&missing).exists());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_locked() {
+        use std::fs;
+        use storage::file::resource::FileResource;
+
+        type FileStorage = GenericStorage<FileResource>;
+
+        let path = std::env::temp_dir().join(format!("golem-locked-{}", std::process::id()));
+        let location = path.display().to_string();
+        fs::write(&path, make_vec(100)).unwrap();
+
+        let items = vec![(location.clone(), 100)];
+        let open = |mode| FileStorage::with_mode("locked".to_string(), items.clone(), mode);
+        let assert_locked = |result: Result<FileStorage>| match result {
+            Ok(_) => panic!("Locked resource should not have been opened"),
+            Err(error) => match error.kind {
+                ErrorKind::Locked(ref l) if *l == location => (),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        };
+
+        let writable = open(OpenMode::ReadWrite).unwrap();
+        assert_locked(open(OpenMode::ReadWrite));
+        assert_locked(open(OpenMode::ReadOnly));
+
+        let serialized = bincode::serialize(&writable).unwrap();
+        let saved: SavedStorage = bincode::deserialize(&serialized).unwrap();
+        assert_locked(saved.open(&LoadOptions::default()));
+        drop(writable);
+
+        let readers = (
+            open(OpenMode::ReadOnly).unwrap(),
+            open(OpenMode::ReadOnly).unwrap(),
+        );
+        assert_locked(open(OpenMode::ReadWrite));
+        drop(readers);
+
+        open(OpenMode::ReadWrite).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_quota() {
+        use std::fs;
+        use std::path::Path;
+        use storage::file::resource::FileResource;
+
+        type FileStorage = GenericStorage<FileResource>;
+
+        let dir = std::env::temp_dir().join(format!("golem-quota-{}", std::process::id()));
+        let location = |name: &str| dir.join(name).display().to_string();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(location("existing"), make_vec(2000)).unwrap();
+
+        let options = CreateOptions {
+            quota: Some(Quota(1000)),
+            ..CreateOptions::default()
+        };
+        let items = vec![(location("existing"), 2000), (location("new"), 500)];
+        match FileStorage::with_options("quota".to_string(), items.clone(), &options) {
+            Ok(_) => panic!("Storage should not have been created"),
+            Err(error) => match error.kind {
+                ErrorKind::QuotaExceeded(2500, 1000) => (),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+        assert!(!Path::new(&location("new")).exists());
+
+        let large = CreateOptions {
+            quota: Some(Quota(2500)),
+            ..CreateOptions::default()
+        };
+        let storage = FileStorage::with_options("quota".to_string(), items, &large).unwrap();
+        assert_eq!(storage.allocated(), 2500);
+
+        let serialized = bincode::serialize(&storage).unwrap();
+        drop(storage);
+        let saved: SavedStorage = bincode::deserialize(&serialized).unwrap();
+        let storage: FileStorage = saved.open(&LoadOptions::default()).unwrap();
+        assert_eq!(storage.allocated(), 2500);
+        drop(storage);
+
+        let items = vec![(location("a"), 600), (location("b"), 600)];
+        match FileStorage::with_options("quota".to_string(), items, &options) {
+            Ok(_) => panic!("Storage should not have been created"),
+            Err(error) => match error.kind {
+                ErrorKind::QuotaExceeded(1200, 1000) => (),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+        assert!(!Path::new(&location("a")).exists());
+
+        let available = fs2::available_space(&dir).unwrap() as usize;
+        let items = vec![(location("a"), 10), (location("b"), available)];
+        match FileStorage::new("space".to_string(), items) {
+            Ok(_) => panic!("Storage should not have been created"),
+            Err(error) => match error.kind {
+                ErrorKind::InsufficientSpace(required, _) => assert_eq!(required, available + 10),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+        assert!(!Path::new(&location("a")).exists());
+
+        // Creating a file under another file fails after "a" was created
+        let items = vec![(location("a"), 10), (location("existing/b"), 10)];
+        assert!(FileStorage::new("rollback".to_string(), items).is_err());
+        assert!(!Path::new(&location("a")).exists());
+        assert!(Path::new(&location("existing")).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
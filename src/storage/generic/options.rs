@@ -0,0 +1,23 @@
+use storage::relocation::Relocation;
+use storage::resource::OpenMode;
+use storage::space::Quota;
+
+/// Options a `GenericStorage` is created with
+#[derive(Clone, Debug, Default)]
+pub struct CreateOptions<O> {
+    pub mode: OpenMode,
+    /// Limit of bytes allocated for all resources, existing ones included
+    pub quota: Option<Quota>,
+    /// Options resources are created and opened with, e.g. encryption keys
+    pub resource: O,
+}
+
+/// Options a saved `GenericStorage` is opened with
+#[derive(Clone, Debug, Default)]
+pub struct LoadOptions<O> {
+    pub mode: OpenMode,
+    /// Mapping of recorded resource locations to the ones opened
+    pub relocation: Option<Relocation>,
+    /// Options resources are opened with, e.g. encryption keys
+    pub resource: O,
+}
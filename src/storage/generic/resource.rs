@@ -1,12 +1,13 @@
 use std::cell::BorrowMutError;
 use std::cell::RefCell;
-use std::ops::Deref;
+use std::ops::{Deref, DerefMut};
 use std::rc::Rc;
+use std::sync::{Arc, Mutex, PoisonError};
 
 use storage::error::Error;
 use storage::error::ErrorKind;
 use storage::resource::*;
-use storage::Size;
+use storage::{Result, Size};
 
 #[macro_export]
 macro_rules! impl_resource_serde {
@@ -36,6 +37,10 @@ macro_rules! impl_resource_serde {
 }
 
 pub type GenericResourcePtr<R> = Rc<RefCell<R>>;
+/// Thread-safe resource pointer, for storages shared across threads
+pub type SharedResourcePtr<R> = Arc<Mutex<R>>;
+/// Thread-safe pointer to a `PositionalResource`, accessed without locking
+pub type PositionalResourcePtr<R> = Arc<R>;
 
 impl<R> ResourcePtr for Rc<RefCell<R>>
 where
@@ -46,6 +51,86 @@ where
     fn new(r: Self::Target) -> Self {
         Rc::new(RefCell::new(r))
     }
+
+    fn read_at(&self, offset: usize, into: &mut [u8]) -> Result<usize> {
+        read_at_exclusive(self, offset, into)
+    }
+
+    fn write_at(&self, offset: usize, from: &[u8]) -> Result<usize> {
+        write_at_exclusive(self, offset, from)
+    }
+}
+
+impl<R> ExclusiveResourcePtr for Rc<RefCell<R>>
+where
+    R: Resource,
+{
+    fn with_mut<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self::Target) -> Result<T>,
+    {
+        let mut borrowed = self.try_borrow_mut()?;
+        f(borrowed.deref_mut())
+    }
+}
+
+impl<R> ResourcePtr for Arc<Mutex<R>>
+where
+    R: Resource,
+{
+    type Target = R;
+
+    fn new(r: Self::Target) -> Self {
+        Arc::new(Mutex::new(r))
+    }
+
+    fn read_at(&self, offset: usize, into: &mut [u8]) -> Result<usize> {
+        read_at_exclusive(self, offset, into)
+    }
+
+    fn write_at(&self, offset: usize, from: &[u8]) -> Result<usize> {
+        write_at_exclusive(self, offset, from)
+    }
+}
+
+impl<R> ExclusiveResourcePtr for Arc<Mutex<R>>
+where
+    R: Resource,
+{
+    fn with_mut<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self::Target) -> Result<T>,
+    {
+        let mut locked = self.lock()?;
+        f(locked.deref_mut())
+    }
+}
+
+impl<R> ResourcePtr for Arc<R>
+where
+    R: PositionalResource,
+{
+    type Target = R;
+
+    fn new(r: Self::Target) -> Self {
+        Arc::new(r)
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        self.deref().as_slice()
+    }
+
+    fn read_at(&self, offset: usize, into: &mut [u8]) -> Result<usize> {
+        read_exact_with(into, |buf, done| {
+            PositionalResource::read_at(self.deref(), offset + done, buf)
+        })
+    }
+
+    fn write_at(&self, offset: usize, from: &[u8]) -> Result<usize> {
+        write_all_with(from, |buf, done| {
+            PositionalResource::write_at(self.deref(), offset + done, buf)
+        })
+    }
 }
 
 impl<R> Size for Rc<RefCell<R>>
@@ -66,6 +151,27 @@ where
     }
 }
 
+impl<R> Size for Arc<R>
+where
+    R: PositionalResource,
+{
+    fn size(&self) -> usize {
+        self.deref().size()
+    }
+}
+
+impl<R> Size for Arc<Mutex<R>>
+where
+    R: Resource,
+{
+    fn size(&self) -> usize {
+        match self.lock() {
+            Ok(locked) => locked.size(),
+            Err(poisoned) => poisoned.into_inner().size(),
+        }
+    }
+}
+
 impl From<BorrowMutError> for Error {
     fn from(_: BorrowMutError) -> Self {
         Error::new(ErrorKind::MemoryError(
@@ -73,3 +179,11 @@ impl From<BorrowMutError> for Error {
         ))
     }
 }
+
+impl<T> From<PoisonError<T>> for Error {
+    fn from(_: PoisonError<T>) -> Self {
+        Error::new(ErrorKind::MemoryError(
+            "Resource pointer error: lock poisoned".to_string(),
+        ))
+    }
+}
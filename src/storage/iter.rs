@@ -1,3 +1,5 @@
+use std::cmp::min;
+
 use storage::Storage;
 use streaming_iterator::StreamingIterator;
 
@@ -9,6 +11,7 @@ where
     size: usize,
     offset: usize,
     buf: Vec<u8>,
+    done: bool,
 }
 
 impl<'s, S> StorageIterator<'s, S>
@@ -21,6 +24,7 @@ where
             size,
             offset: 0,
             buf: vec![0 as u8; size],
+            done: false,
         }
     }
 }
@@ -32,23 +36,31 @@ where
     type Item = Vec<u8>;
 
     fn advance(&mut self) {
+        let remaining = self.storage.size().saturating_sub(self.offset);
+        if remaining == 0 {
+            self.done = true;
+            return;
+        }
+
+        self.buf.resize(min(self.size, remaining), 0);
         let read = self.storage.read(self.offset, &mut self.buf[..]);
 
         match read {
             Ok(n) => {
                 self.offset += n;
-                if n != self.size {
+                if n != self.buf.len() {
                     self.buf.truncate(n as usize);
                 }
             }
             Err(_) => {
                 self.offset = self.storage.size();
+                self.done = true;
             }
         }
     }
 
     fn get(&self) -> Option<&Self::Item> {
-        if self.offset >= self.size {
+        if self.done {
             None
         } else {
             Some(&self.buf)
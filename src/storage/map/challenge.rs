@@ -0,0 +1,194 @@
+use std::collections::BTreeSet;
+
+use merkle_tree::digest::Digest;
+use merkle_tree::mode::Mode;
+use merkle_tree::proof::{Leaf, MultiProof};
+use merkle_tree::Array;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::error::{Error, ErrorKind};
+
+/// Proof-of-storage challenge: a nonce and the pieces derived from it, which
+/// the prover answers by sending them
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Challenge {
+    pub nonce: Array,
+    pub piece_count: usize,
+    /// sorted, unique indices of challenged pieces
+    pub pieces: Vec<usize>,
+}
+
+impl Challenge {
+    pub const NONCE_SIZE: usize = 32;
+
+    /// Create a challenge of `count` pieces with a random nonce
+    pub fn new<D>(piece_count: usize, count: usize) -> Self
+    where
+        D: Digest,
+    {
+        let mut nonce = vec![0u8; Self::NONCE_SIZE];
+        rand::thread_rng().fill(&mut nonce[..]);
+        Self::from_nonce::<D>(nonce, piece_count, count)
+    }
+
+    /// Derive challenged pieces from the nonce, by hashing the nonce with
+    /// a counter until enough distinct pieces are drawn
+    pub fn from_nonce<D>(nonce: Array, piece_count: usize, count: usize) -> Self
+    where
+        D: Digest,
+    {
+        let count = std::cmp::min(count, piece_count);
+        let mut pieces = BTreeSet::new();
+        let mut digest = D::new();
+        let mut counter: u64 = 0;
+
+        while pieces.len() < count {
+            digest.input(&nonce);
+            digest.input(counter.to_le_bytes());
+            let hash = digest.result();
+
+            let mut value = [0u8; 8];
+            value.copy_from_slice(&hash[..8]);
+            pieces.insert((u64::from_le_bytes(value) % piece_count as u64) as usize);
+            counter += 1;
+        }
+
+        Challenge {
+            nonce,
+            piece_count,
+            pieces: pieces.into_iter().collect(),
+        }
+    }
+}
+
+/// Answer to a `Challenge`: a download of the challenged pieces, i.e. their
+/// data and a proof of them. Responses grow with the number of challenged
+/// pieces and their size.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Response {
+    pub pieces: Vec<Vec<u8>>,
+    pub proof: MultiProof,
+}
+
+impl Response {
+    /// Check that the response answers the challenge and that the piece
+    /// data is proven against the root
+    pub fn verify_against_root<D>(
+        &self,
+        challenge: &Challenge,
+        root: &[u8],
+        mode: Mode,
+    ) -> Result<(), Error>
+    where
+        D: Digest,
+    {
+        if self.proof.leaf_count != challenge.piece_count
+            || self.proof.leaf_indices != challenge.pieces
+            || self.pieces.len() != challenge.pieces.len()
+        {
+            return Err(Error::new(ErrorKind::ChallengeMismatch));
+        }
+
+        let leaves: Vec<Leaf> = self.pieces.iter().map(|data| Leaf::Data(data)).collect();
+        self.proof.verify_against_root::<D>(root, &leaves, mode)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use merkle_tree::digest::sha256::Sha256;
+    use storage::generic::GenericStorage;
+    use storage::map::chunk::Layout;
+    use storage::map::StorageMap;
+    use storage::tests::common::resource::TestResource;
+
+    type TestStorageMap = StorageMap<GenericStorage<TestResource>, Sha256>;
+
+    fn storage_map(name: &str, mode: Mode) -> TestStorageMap {
+        let resources = (0..10)
+            .map(|n| (format!("{}_{}", name, n), 10000))
+            .collect();
+        TestStorageMap::new(name.to_string(), resources, &Layout::fixed(4096), mode).unwrap()
+    }
+
+    #[test]
+    fn test_from_nonce() {
+        let nonce = vec![7u8; Challenge::NONCE_SIZE];
+        let challenge = Challenge::from_nonce::<Sha256>(nonce.clone(), 100, 10);
+
+        assert_eq!(challenge.pieces.len(), 10);
+        assert!(challenge.pieces.windows(2).all(|w| w[0] < w[1]));
+        assert!(challenge.pieces.iter().all(|p| *p < 100));
+        assert_eq!(challenge, Challenge::from_nonce::<Sha256>(nonce, 100, 10));
+
+        let challenge = Challenge::new::<Sha256>(5, 10);
+        assert_eq!(challenge.pieces, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_respond() {
+        let map = storage_map("prover", Mode::Rfc6962);
+        let challenge = map.challenge(4);
+        let response = map.respond(&challenge).unwrap();
+
+        response
+            .verify_against_root::<Sha256>(&challenge, &map.root().unwrap(), map.mode())
+            .unwrap();
+        assert_eq!(map.respond(&challenge).unwrap().pieces, response.pieces);
+        assert_eq!(response.pieces.len(), 4);
+        assert_eq!(response.pieces[0].len(), 4096);
+    }
+
+    #[test]
+    fn test_respond_errors() {
+        let map = storage_map("prover", Mode::Plain);
+        let root = map.root().unwrap();
+        let challenge = map.challenge(4);
+        let verify = |response: &Response| {
+            response
+                .verify_against_root::<Sha256>(&challenge, &root, Mode::Plain)
+                .unwrap_err()
+                .kind
+        };
+
+        let mut response = map.respond(&challenge).unwrap();
+        response.pieces.pop();
+        match verify(&response) {
+            ErrorKind::ChallengeMismatch => (),
+            kind => panic!("Invalid error kind: {:?}", kind),
+        }
+
+        let other = map.challenge(5);
+        match verify(&map.respond(&other).unwrap()) {
+            ErrorKind::ChallengeMismatch => (),
+            kind => panic!("Invalid error kind: {:?}", kind),
+        }
+
+        let mut response = map.respond(&challenge).unwrap();
+        response.proof.leaf_hashes[0] = vec![0; 32];
+        match verify(&response) {
+            ErrorKind::MerkleTreeProofError(_) => (),
+            kind => panic!("Invalid error kind: {:?}", kind),
+        }
+
+        let mut response = map.respond(&challenge).unwrap();
+        response.pieces[1] = vec![0; response.pieces[1].len()];
+        match verify(&response) {
+            ErrorKind::MerkleTreeProofError(_) => (),
+            kind => panic!("Invalid error kind: {:?}", kind),
+        }
+
+        let mut invalid = challenge.clone();
+        invalid.pieces.push(invalid.piece_count);
+        match map.respond(&invalid) {
+            Ok(_) => panic!("Invalid challenge should not have been answered"),
+            Err(error) => match error.kind {
+                ErrorKind::PieceDoesNotExist(_) => (),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+    }
+}
@@ -1,18 +1,112 @@
 use std::cmp::{max, min};
+use std::ops::Range;
 
 use bit_vec::BitVec;
 use bit_vec_serde::BitVecSerde;
 use serde::{Deserialize, Serialize};
 
+use super::error::{Error, ErrorKind};
+
 #[inline(always)]
 fn div_upper(value: usize, by: usize) -> usize {
     (value + by - 1) / by
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PieceSize {
+    /// Use the given piece size
+    Fixed(usize),
+    /// Derive the piece size from a target piece count
+    Count(usize),
+}
+
+/// Piece and chunk size policy of a `ChunkMap`. The resulting piece size
+/// is always clamped to the `[min_piece_size, max_piece_size]` bounds.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Layout {
+    pub piece_size: PieceSize,
+    pub chunks_in_piece: usize,
+    pub min_piece_size: usize,
+    pub max_piece_size: usize,
+}
+
+impl Layout {
+    pub const MIN_PIECE_SIZE: usize = 16384;
+    pub const MAX_PIECE_SIZE: usize = 1 << 22;
+    pub const PIECE_COUNT: usize = 1 << 10;
+    pub const CHUNKS_IN_PIECE: usize = 4;
+
+    pub fn fixed(piece_size: usize) -> Self {
+        Layout {
+            piece_size: PieceSize::Fixed(piece_size),
+            chunks_in_piece: Self::CHUNKS_IN_PIECE,
+            min_piece_size: piece_size,
+            max_piece_size: piece_size,
+        }
+    }
+
+    pub fn piece_count(piece_count: usize) -> Self {
+        Layout {
+            piece_size: PieceSize::Count(piece_count),
+            ..Layout::default()
+        }
+    }
+
+    pub fn bounds(self, min_piece_size: usize, max_piece_size: usize) -> Self {
+        Layout {
+            min_piece_size,
+            max_piece_size,
+            ..self
+        }
+    }
+
+    pub fn chunks_in_piece(self, chunks_in_piece: usize) -> Self {
+        Layout {
+            chunks_in_piece,
+            ..self
+        }
+    }
+
+    fn piece_size_for(&self, total_size: usize) -> Result<usize, Error> {
+        if self.chunks_in_piece == 0
+            || self.min_piece_size == 0
+            || self.min_piece_size > self.max_piece_size
+        {
+            return Err(Error::new(ErrorKind::InvalidLayout(*self)));
+        }
+
+        let value = match self.piece_size {
+            PieceSize::Fixed(piece_size) => piece_size,
+            PieceSize::Count(0) => return Err(Error::new(ErrorKind::InvalidLayout(*self))),
+            PieceSize::Count(piece_count) => div_upper(total_size, piece_count).next_power_of_two(),
+        };
+
+        let piece_size = max(min(value, self.max_piece_size), self.min_piece_size);
+        if piece_size % self.chunks_in_piece != 0 {
+            return Err(Error::new(ErrorKind::InvalidLayout(*self)));
+        }
+        Ok(piece_size)
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            piece_size: PieceSize::Count(Self::PIECE_COUNT),
+            chunks_in_piece: Self::CHUNKS_IN_PIECE,
+            min_piece_size: Self::MIN_PIECE_SIZE,
+            max_piece_size: Self::MAX_PIECE_SIZE,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
+#[serde(from = "SavedChunkMap")]
 pub(super) struct ChunkMap {
     #[serde(with = "BitVecSerde")]
     pub bitmap: BitVec,
+    pub layout: Layout,
+    pub total_size: usize,
     pub chunk_size: usize,
     pub chunk_count: usize,
     pub piece_size: usize,
@@ -21,40 +115,203 @@ pub(super) struct ChunkMap {
 }
 
 impl ChunkMap {
-    pub const MIN_PIECE_SIZE: usize = 16384;
-    pub const MAX_PIECE_SIZE: usize = 1 << 10;
-
-    pub fn new(total_size: usize, all_set: bool) -> Self {
-        let piece_size = Self::piece_size(total_size);
-        let chunk_size = Self::chunk_size(piece_size);
+    pub fn new(total_size: usize, layout: &Layout, all_set: bool) -> Result<Self, Error> {
+        let piece_size = layout.piece_size_for(total_size)?;
+        let chunks_in_piece = layout.chunks_in_piece;
+        let chunk_size = piece_size / chunks_in_piece;
         let piece_count = div_upper(total_size, piece_size);
-        let chunks_in_piece = div_upper(piece_size, chunk_size);
-        let chunk_count = piece_count * chunks_in_piece;
+        let chunk_count = div_upper(total_size, chunk_size);
         let bitmap = BitVec::from_elem(chunk_count, all_set);
 
-        ChunkMap {
+        Ok(ChunkMap {
             bitmap,
+            layout: *layout,
+            total_size,
             chunk_size,
             chunk_count,
             piece_size,
             piece_count,
             chunks_in_piece,
-        }
+        })
     }
 
-    fn piece_size(total_size: usize) -> usize {
-        let value = if total_size > 0 {
-            let bits: usize = std::mem::size_of::<usize>() * 8;
-            let zeros: usize = total_size.leading_zeros() as usize;
-            1 << (bits - zeros - 1)
-        } else {
-            0
-        };
-        max(min(value, Self::MAX_PIECE_SIZE), Self::MIN_PIECE_SIZE)
+    pub fn piece_chunks(&self, piece: usize) -> Range<usize> {
+        let first_chunk = min(piece * self.chunks_in_piece, self.chunk_count);
+        first_chunk..min(first_chunk + self.chunks_in_piece, self.chunk_count)
+    }
+
+    /// Length of the chunk in bytes; the last chunk may be shorter
+    #[inline]
+    pub fn chunk_len(&self, chunk: usize) -> usize {
+        Self::len(self.total_size, self.chunk_size, chunk)
+    }
+
+    /// Length of the piece in bytes; the last piece may be shorter
+    #[inline]
+    pub fn piece_len(&self, piece: usize) -> usize {
+        Self::len(self.total_size, self.piece_size, piece)
     }
 
     #[inline(always)]
-    fn chunk_size(piece_size: usize) -> usize {
-        piece_size >> 2
+    fn len(total_size: usize, size: usize, index: usize) -> usize {
+        let offset = index.saturating_mul(size);
+        min(size, total_size.saturating_sub(offset))
+    }
+}
+
+/// Serialized form of a `ChunkMap`
+#[derive(Deserialize)]
+struct SavedChunkMap {
+    #[serde(with = "BitVecSerde")]
+    bitmap: BitVec,
+    layout: Layout,
+    total_size: usize,
+    chunk_size: usize,
+    chunk_count: usize,
+    piece_size: usize,
+    piece_count: usize,
+    chunks_in_piece: usize,
+}
+
+impl From<SavedChunkMap> for ChunkMap {
+    fn from(chunks: SavedChunkMap) -> Self {
+        // Serialized bitmaps are padded to whole bytes
+        let mut bitmap = chunks.bitmap;
+        bitmap.truncate(chunks.chunk_count);
+
+        ChunkMap {
+            bitmap,
+            layout: chunks.layout,
+            total_size: chunks.total_size,
+            chunk_size: chunks.chunk_size,
+            chunk_count: chunks.chunk_count,
+            piece_size: chunks.piece_size,
+            piece_count: chunks.piece_count,
+            chunks_in_piece: chunks.chunks_in_piece,
+        }
+    }
+}
+
+/// Serialized form of chunk maps saved before layouts were selectable
+#[derive(Deserialize)]
+pub(super) struct LegacyChunkMap {
+    #[serde(with = "BitVecSerde")]
+    pub bitmap: BitVec,
+    pub chunk_size: usize,
+    pub chunk_count: usize,
+    pub piece_size: usize,
+    pub piece_count: usize,
+    pub chunks_in_piece: usize,
+}
+
+impl LegacyChunkMap {
+    /// Map of `total_size` bytes with the saved piece and chunk sizes.
+    /// Legacy maps padded chunks to whole pieces; padding chunks are dropped.
+    pub fn upgrade(self, total_size: usize) -> Result<ChunkMap, Error> {
+        let layout = Layout::fixed(self.piece_size).chunks_in_piece(self.chunks_in_piece);
+        let mut chunks = ChunkMap::new(total_size, &layout, false)?;
+
+        if chunks.chunk_size != self.chunk_size
+            || chunks.piece_count != self.piece_count
+            || chunks.chunk_count > self.chunk_count
+        {
+            return Err(Error::new(ErrorKind::InvalidLayout(layout)));
+        }
+
+        chunks.bitmap = self.bitmap;
+        chunks.bitmap.truncate(chunks.chunk_count);
+        Ok(chunks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_layout() {
+        let layout = Layout::default();
+
+        let map = ChunkMap::new(1024, &layout, true).unwrap();
+        assert_eq!(map.piece_size, Layout::MIN_PIECE_SIZE);
+        assert_eq!(
+            map.chunk_size,
+            Layout::MIN_PIECE_SIZE / Layout::CHUNKS_IN_PIECE
+        );
+        assert_eq!(map.piece_count, 1);
+
+        let map = ChunkMap::new(1 << 26, &layout, true).unwrap();
+        assert_eq!(map.piece_size, 1 << 16);
+        assert_eq!(map.piece_count, Layout::PIECE_COUNT);
+
+        let map = ChunkMap::new(1 << 34, &layout, false).unwrap();
+        assert_eq!(map.piece_size, Layout::MAX_PIECE_SIZE);
+        assert_eq!(map.chunk_count, map.piece_count * Layout::CHUNKS_IN_PIECE);
+    }
+
+    #[test]
+    fn test_trailing_lengths() {
+        let layout = Layout::fixed(4096);
+        let map = ChunkMap::new(10000, &layout, false).unwrap();
+
+        assert_eq!(map.piece_count, 3);
+        assert_eq!(map.chunk_count, 10);
+        assert_eq!(map.bitmap.len(), 10);
+
+        assert_eq!(map.piece_len(0), 4096);
+        assert_eq!(map.piece_len(2), 10000 - 8192);
+        assert_eq!(map.piece_len(3), 0);
+
+        assert_eq!(map.chunk_len(0), 1024);
+        assert_eq!(map.chunk_len(9), 10000 - 9216);
+        assert_eq!(map.chunk_len(10), 0);
+
+        assert_eq!(map.piece_chunks(0), 0..4);
+        assert_eq!(map.piece_chunks(2), 8..10);
+        assert_eq!(map.piece_chunks(3), 10..10);
+    }
+
+    #[test]
+    fn test_fixed_layout() {
+        let layout = Layout::fixed(3000).chunks_in_piece(3);
+        let map = ChunkMap::new(10000, &layout, true).unwrap();
+
+        assert_eq!(map.layout, layout);
+        assert_eq!(map.piece_size, 3000);
+        assert_eq!(map.chunk_size, 1000);
+        assert_eq!(map.piece_count, 4);
+        assert_eq!(map.chunk_count, 10);
+    }
+
+    #[test]
+    fn test_bounded_layout() {
+        let layout = Layout::piece_count(4).bounds(1024, 4096);
+
+        assert_eq!(ChunkMap::new(1024, &layout, true).unwrap().piece_size, 1024);
+        assert_eq!(ChunkMap::new(8192, &layout, true).unwrap().piece_size, 2048);
+        assert_eq!(
+            ChunkMap::new(65536, &layout, true).unwrap().piece_size,
+            4096
+        );
+    }
+
+    #[test]
+    fn test_invalid_layout() {
+        let layouts = [
+            Layout::fixed(3000).chunks_in_piece(7),
+            Layout::fixed(4096).chunks_in_piece(0),
+            Layout::piece_count(0),
+            Layout::piece_count(4).bounds(4096, 1024),
+        ];
+
+        for layout in layouts.iter() {
+            match ChunkMap::new(8192, layout, true) {
+                Ok(_) => panic!("Map should not have been created with {:?}", layout),
+                Err(error) => match error.kind {
+                    ErrorKind::InvalidLayout(_) => (),
+                    kind => panic!("Invalid error kind: {:?}", kind),
+                },
+            }
+        }
     }
 }
\ No newline at end of file
@@ -1,11 +1,19 @@
 use error;
 use storage::error::ErrorKind as StorageErrorKind;
+use storage::map::chunk::Layout;
 
 #[derive(Debug)]
 pub enum ErrorKind {
+    ChallengeMismatch,
     ChunkAlreadyExists(usize),
     ChunkDoesNotExist(usize),
     ChunkOutOfRange(usize),
+    ChunkSizeMismatch(usize, usize),
+    PieceDoesNotExist(usize),
+    PieceCountMismatch(usize, usize),
+    PieceHashMismatch(usize),
+    InvalidLayout(Layout),
+    RangeOutOfBounds(usize, usize),
     StorageError(StorageErrorKind),
     MerkleTreeError(merkle_tree::error::Error),
     MerkleTreeProofError(merkle_tree::proof::error::Error),
@@ -1,33 +1,191 @@
+pub mod challenge;
 pub mod chunk;
 pub mod error;
+pub mod picker;
+pub mod range;
+
+use std::cmp::min;
+use std::ops::Range;
 
 use serde::{Deserialize, Serialize};
+use merkle_tree::diff::NodeRequest;
 use merkle_tree::digest::sha512::Sha512;
-use merkle_tree::proof::{Proof, Provable};
-use merkle_tree::tree::MerkleTree;
+use merkle_tree::digest::{Algorithm, Digest};
+use merkle_tree::mode::Mode;
+use merkle_tree::proof::{MultiProof, MultiProvable, Proof, Provable};
+use merkle_tree::tree::{LegacyMerkleTree, MerkleTree};
+use merkle_tree::Array;
 
-use storage::{Storage, StorageId};
-use self::chunk::ChunkMap;
+use storage::generic::options::LoadOptions;
+use storage::generic::{GenericStorage, LegacySavedStorage, SavedStorage};
+use storage::resource::{OpenMode, Resource, ResourcePtr};
+use storage::{Size, Storage, StorageId};
+use self::challenge::{Challenge, Response};
+use self::chunk::{ChunkMap, Layout, LegacyChunkMap};
 use self::error::*;
+use self::picker::{Picker, Strategy};
+use self::range::ByteRangeProof;
 
 #[derive(Serialize, Deserialize)]
-pub struct StorageMap<S>
+#[serde(bound(serialize = "S: Serialize", deserialize = "S: Deserialize<'de>"))]
+pub struct StorageMap<S, D = Sha512>
 where
     S: Storage,
+    D: Digest,
 {
-    tree: MerkleTree<Sha512>,
+    tree: MerkleTree<D>,
     chunks: ChunkMap,
     storage: S,
 }
 
-impl<S> StorageMap<S>
+/// Serialized form of a `StorageMap` of a `GenericStorage`, before the
+/// resources of its storage are opened
+#[derive(Deserialize)]
+#[serde(bound = "")]
+pub struct SavedStorageMap<D = Sha512>
+where
+    D: Digest,
+{
+    tree: MerkleTree<D>,
+    chunks: ChunkMap,
+    storage: SavedStorage,
+}
+
+impl<D> SavedStorageMap<D>
+where
+    D: Digest,
+{
+    /// Open the resources of the saved storage with `options`
+    pub fn open<R, P>(
+        self,
+        options: &LoadOptions<R::Options>,
+    ) -> Result<StorageMap<GenericStorage<R, P>, D>, Error>
+    where
+        R: Resource,
+        P: ResourcePtr<Target = R>,
+    {
+        Ok(StorageMap {
+            tree: self.tree,
+            chunks: self.chunks,
+            storage: self.storage.open(options)?,
+        })
+    }
+}
+
+/// Serialized form of a `StorageMap` of a `GenericStorage` saved before
+/// digests, hashing modes and layouts were selectable, which always used
+/// SHA-512 in `Mode::Plain`
+#[derive(Deserialize)]
+pub struct LegacySavedStorageMap {
+    tree: LegacyMerkleTree<Sha512>,
+    chunks: LegacyChunkMap,
+    storage: LegacySavedStorage,
+}
+
+impl LegacySavedStorageMap {
+    /// Open the resources of the saved storage with `options`
+    pub fn open<R, P>(
+        self,
+        options: &LoadOptions<R::Options>,
+    ) -> Result<StorageMap<GenericStorage<R, P>, Sha512>, Error>
+    where
+        R: Resource,
+        P: ResourcePtr<Target = R>,
+    {
+        let storage = self.storage.open(options)?;
+        let chunks = self.chunks.upgrade(storage.size())?;
+        let mut tree = MerkleTree::from(self.tree);
+
+        // Legacy maps were saved with trees of no leaves, but always held
+        // all data, so their trees are rebuilt
+        if tree.leaf_count() != chunks.piece_count {
+            if !chunks.bitmap.all() {
+                return Err(Error::new(ErrorKind::PieceCountMismatch(
+                    chunks.piece_count,
+                    tree.leaf_count(),
+                )));
+            }
+
+            let hashes = StorageMap::<_, Sha512>::hash_pieces(&storage, &chunks, Mode::Plain)?;
+            tree = MerkleTree::from_hashes(&hashes[..], Mode::Plain)?;
+        }
+
+        Ok(StorageMap {
+            tree,
+            chunks,
+            storage,
+        })
+    }
+}
+
+impl<S, D> StorageMap<S, D>
 where
     S: Storage,
+    D: Digest,
 {
-    pub fn new(name: StorageId, items: Vec<(String, usize)>) -> Result<Self, Error> {
-        let storage = S::new(name, items)?;
-        let chunks = ChunkMap::new(storage.size(), true);
-        let tree = MerkleTree::<Sha512>::from(storage.iter(chunks.piece_size));
+    pub fn new(
+        name: StorageId,
+        items: Vec<(String, usize)>,
+        layout: &Layout,
+        mode: Mode,
+    ) -> Result<Self, Error> {
+        Self::from_storage(S::new(name, items)?, layout, mode)
+    }
+
+    /// Create a map of existing or new resources opened in `open_mode`.
+    /// Pieces of read-only storages are hashed without copying them when
+    /// their resources are mapped.
+    pub fn new_with_mode(
+        name: StorageId,
+        items: Vec<(String, usize)>,
+        layout: &Layout,
+        mode: Mode,
+        open_mode: OpenMode,
+    ) -> Result<Self, Error> {
+        Self::from_storage(S::with_mode(name, items, open_mode)?, layout, mode)
+    }
+
+    /// Create a map of an existing storage, hashing its data
+    pub fn from_storage(storage: S, layout: &Layout, mode: Mode) -> Result<Self, Error> {
+        let chunks = ChunkMap::new(storage.size(), layout, true)?;
+        let hashes = Self::hash_pieces(&storage, &chunks, mode)?;
+        let tree = MerkleTree::<D>::from_hashes(&hashes[..], mode)?;
+
+        Ok(StorageMap {
+            tree,
+            chunks,
+            storage,
+        })
+    }
+
+    pub fn from_hashes(
+        name: StorageId,
+        items: Vec<(String, usize)>,
+        layout: &Layout,
+        mode: Mode,
+        hashes: Vec<Array>,
+    ) -> Result<Self, Error> {
+        Self::from_storage_hashes(S::new(name, items)?, layout, mode, hashes)
+    }
+
+    /// Create a map of an existing storage, to be filled with data matching
+    /// the expected piece `hashes`
+    pub fn from_storage_hashes(
+        storage: S,
+        layout: &Layout,
+        mode: Mode,
+        hashes: Vec<Array>,
+    ) -> Result<Self, Error> {
+        let chunks = ChunkMap::new(storage.size(), layout, false)?;
+
+        if hashes.len() != chunks.piece_count {
+            return Err(Error::new(ErrorKind::PieceCountMismatch(
+                chunks.piece_count,
+                hashes.len(),
+            )));
+        }
+
+        let tree = MerkleTree::<D>::from_hashes(&hashes[..], mode)?;
 
         Ok(StorageMap {
             tree,
@@ -41,32 +199,77 @@ where
         self.storage.name()
     }
 
+    #[inline]
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
     pub fn read_chunk(&self, chunk: usize) -> Result<Vec<u8>, Error> {
         if !self.has_chunk(chunk) {
             return Err(Error::new(ErrorKind::ChunkDoesNotExist(chunk)));
         }
 
         let offset = chunk * self.chunks.chunk_size;
-        self.read_storage(offset, self.chunks.chunk_size)
+        self.read_storage(offset, self.chunks.chunk_len(chunk))
     }
 
     pub fn write_chunk(&mut self, chunk: usize, data: &Vec<u8>) -> Result<(), Error> {
+        if chunk >= self.chunks.chunk_count {
+            return Err(Error::new(ErrorKind::ChunkOutOfRange(chunk)));
+        }
         if self.has_chunk(chunk) {
             return Err(Error::new(ErrorKind::ChunkAlreadyExists(chunk)));
         }
 
+        let chunk_len = self.chunks.chunk_len(chunk);
+        if data.len() != chunk_len {
+            return Err(Error::new(ErrorKind::ChunkSizeMismatch(
+                chunk_len,
+                data.len(),
+            )));
+        }
+
         let offset = chunk * self.chunks.chunk_size;
         self.storage.write(offset, &data[..])?;
         self.chunks.bitmap.set(chunk, true);
 
         let piece_num = self.piece_from_chunk(chunk);
         if self.has_piece(piece_num) {
-            self.update_tree(piece_num)?;
+            let result = self.verify_piece(piece_num);
+            if result.is_err() {
+                self.clear_piece(piece_num);
+            }
+            return result;
         }
 
         Ok(())
     }
 
+    #[inline]
+    pub fn algorithm(&self) -> Algorithm {
+        D::algorithm()
+    }
+
+    #[inline]
+    pub fn mode(&self) -> Mode {
+        self.tree.mode()
+    }
+
+    #[inline]
+    pub fn layout(&self) -> &Layout {
+        &self.chunks.layout
+    }
+
+    #[inline]
+    pub fn root(&self) -> Option<Array> {
+        self.tree.root()
+    }
+
+    #[inline]
+    pub fn hashes(&self) -> Vec<Array> {
+        self.tree.leaves()
+    }
+
     #[inline]
     pub fn has_chunk(&self, chunk_num: usize) -> bool {
         match self.chunks.bitmap.get(chunk_num) {
@@ -76,34 +279,160 @@ where
     }
 
     pub fn has_piece(&self, piece_num: usize) -> bool {
-        let first_chunk = (piece_num * self.chunks.piece_size) / self.chunks.chunk_size;
-        (first_chunk..first_chunk + self.chunks.chunks_in_piece)
-            .into_iter()
-            .all(|i| self.has_chunk(i))
+        piece_num < self.chunks.piece_count
+            && self
+                .chunks
+                .piece_chunks(piece_num)
+                .all(|i| self.has_chunk(i))
+    }
+
+    /// Chunks of the piece, e.g. for requesting pieces found by `diff`
+    #[inline]
+    pub fn piece_chunks(&self, piece_num: usize) -> Range<usize> {
+        self.chunks.piece_chunks(piece_num)
+    }
+
+    #[inline]
+    pub fn node_hashes(&self, request: &NodeRequest) -> Result<Vec<Option<Array>>, Error> {
+        Ok(self.tree.node_hashes(request)?)
+    }
+
+    /// Indices of pieces that differ from the other storage
+    pub fn diff<T>(&self, other: &StorageMap<T, D>) -> Result<Vec<usize>, Error>
+    where
+        T: Storage,
+    {
+        Ok(self.tree.diff(&other.tree)?)
+    }
+
+    #[inline]
+    pub fn missing_chunks(&self) -> Vec<usize> {
+        Picker::new(&self.chunks).missing_chunks()
+    }
+
+    #[inline]
+    pub fn missing_ranges(&self) -> Vec<Range<usize>> {
+        Picker::new(&self.chunks).missing_ranges()
+    }
+
+    #[inline]
+    pub fn completed_pieces(&self) -> usize {
+        Picker::new(&self.chunks).completed_pieces()
+    }
+
+    #[inline]
+    pub fn pick_chunks(&self, count: usize, strategy: &Strategy) -> Vec<usize> {
+        Picker::new(&self.chunks).pick(count, strategy)
+    }
+
+    /// Prove `len` bytes of the storage starting at `offset`. All pieces
+    /// overlapping the range must be present.
+    pub fn prove_range(&self, offset: usize, len: usize) -> Result<ByteRangeProof, Error> {
+        let range_end = match offset.checked_add(len) {
+            Some(end) if len > 0 && end <= self.chunks.total_size => end,
+            _ => return Err(Error::new(ErrorKind::RangeOutOfBounds(offset, len))),
+        };
+
+        let piece_size = self.chunks.piece_size;
+        let pieces = offset / piece_size..(range_end - 1) / piece_size + 1;
+        if let Some(piece) = pieces.clone().find(|piece| !self.has_piece(*piece)) {
+            return Err(Error::new(ErrorKind::PieceDoesNotExist(piece)));
+        }
+
+        let start = pieces.start * piece_size;
+        let end = min(pieces.end * piece_size, self.chunks.total_size);
+        let head = self.read_storage(start, offset - start)?;
+        let tail = self.read_storage(range_end, end - range_end)?;
+        let proof = self.tree.prove_range(pieces)?;
+
+        Ok(ByteRangeProof { head, tail, proof })
+    }
+
+    /// Create a challenge of `count` pieces of this storage
+    pub fn challenge(&self, count: usize) -> Challenge {
+        Challenge::new::<D>(self.chunks.piece_count, count)
+    }
+
+    /// Answer a challenge with the data of the challenged pieces and a
+    /// proof of them
+    pub fn respond(&self, challenge: &Challenge) -> Result<Response, Error> {
+        if challenge.piece_count != self.chunks.piece_count {
+            return Err(Error::new(ErrorKind::ChallengeMismatch));
+        }
+
+        let mut pieces = Vec::with_capacity(challenge.pieces.len());
+        for piece in challenge.pieces.iter() {
+            if !self.has_piece(*piece) {
+                return Err(Error::new(ErrorKind::PieceDoesNotExist(*piece)));
+            }
+
+            let offset = piece * self.chunks.piece_size;
+            let data = self.read_storage(offset, self.chunks.piece_len(*piece))?;
+            pieces.push(data);
+        }
+
+        let proof = self.tree.prove_multi(&challenge.pieces)?;
+        Ok(Response { pieces, proof })
     }
 
     fn read_storage(&self, offset: usize, size: usize) -> Result<Vec<u8>, Error> {
         let mut buffer = vec![0 as u8; size];
-        self.storage.read(offset, &mut buffer[..])?;
+        if size > 0 {
+            self.storage.read(offset, &mut buffer[..])?;
+        }
         Ok(buffer)
     }
 
-    fn update_tree(&mut self, piece_num: usize) -> Result<(), Error> {
+    /// Hash every piece of the storage, borrowing mapped pieces directly
+    /// and copying the remaining ones through a single buffer
+    fn hash_pieces(storage: &S, chunks: &ChunkMap, mode: Mode) -> Result<Vec<Array>, Error> {
+        let mut digest = D::new();
+        let mut buffer = Vec::new();
+
+        (0..chunks.piece_count)
+            .map(|piece| {
+                let offset = piece * chunks.piece_size;
+                let len = chunks.piece_len(piece);
+
+                let hash = match storage.read_slice(offset, len)? {
+                    Some(slice) => mode.hash_leaf(&mut digest, slice),
+                    None => {
+                        buffer.resize(len, 0u8);
+                        storage.read(offset, &mut buffer[..])?;
+                        mode.hash_leaf(&mut digest, &buffer)
+                    }
+                };
+                Ok(hash)
+            })
+            .collect()
+    }
+
+    fn verify_piece(&self, piece_num: usize) -> Result<(), Error> {
         let offset = piece_num * self.chunks.piece_size;
-        let buffer = self.read_storage(offset, self.chunks.piece_size)?;
-        self.tree.set(piece_num, &buffer)?;
+        let buffer = self.read_storage(offset, self.chunks.piece_len(piece_num))?;
+
+        if self.tree.hash_leaf(&buffer) != self.tree.get(piece_num)? {
+            return Err(Error::new(ErrorKind::PieceHashMismatch(piece_num)));
+        }
         Ok(())
     }
 
+    fn clear_piece(&mut self, piece_num: usize) {
+        for chunk in self.chunks.piece_chunks(piece_num) {
+            self.chunks.bitmap.set(chunk, false);
+        }
+    }
+
     #[inline]
     fn piece_from_chunk(&self, chunk_num: usize) -> usize {
         (chunk_num * self.chunks.chunk_size) / self.chunks.piece_size
     }
 }
 
-impl<S> Provable<Error> for StorageMap<S>
+impl<S, D> Provable<Error> for StorageMap<S, D>
 where
     S: Storage,
+    D: Digest,
 {
     fn prove(&self, leaf_index: usize) -> Result<Proof, Error> {
         let proof = self.tree.prove(leaf_index)?;
@@ -115,3 +444,564 @@ where
         Ok(())
     }
 }
+
+impl<S, D> MultiProvable<Error> for StorageMap<S, D>
+where
+    S: Storage,
+    D: Digest,
+{
+    fn prove_multi(&self, leaf_indices: &[usize]) -> Result<MultiProof, Error> {
+        let proof = self.tree.prove_multi(leaf_indices)?;
+        Ok(proof)
+    }
+
+    fn verify_multi(&self, proof: &MultiProof) -> Result<(), Error> {
+        self.tree.verify_multi(proof)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use merkle_tree::digest::sha256::Sha256;
+    use storage::generic::GenericStorage;
+    use storage::tests::common::resource::TestResource;
+
+    type TestStorageMap = StorageMap<GenericStorage<TestResource>>;
+
+    const RESOURCE_SIZE: usize = 16384;
+
+    fn layout() -> Layout {
+        Layout::fixed(RESOURCE_SIZE)
+    }
+
+    fn resources(prefix: &str, count: usize) -> Vec<(String, usize)> {
+        (0..count)
+            .map(|n| (format!("{}_{}", prefix, n), RESOURCE_SIZE))
+            .collect()
+    }
+
+    #[test]
+    fn test_new() {
+        let map = TestStorageMap::new(
+            "Test map".to_string(),
+            resources("source", 4),
+            &layout(),
+            Mode::Plain,
+        )
+        .unwrap();
+
+        assert_eq!(map.hashes().len(), map.chunks.piece_count);
+        assert!((0..map.chunks.chunk_count).all(|c| map.has_chunk(c)));
+        assert!((0..map.chunks.piece_count).all(|p| map.has_piece(p)));
+    }
+
+    #[test]
+    fn test_from_hashes() {
+        let source = TestStorageMap::new(
+            "Source".to_string(),
+            resources("source", 4),
+            &layout(),
+            Mode::Plain,
+        )
+        .unwrap();
+        let mut target = TestStorageMap::from_hashes(
+            "Target".to_string(),
+            resources("target", 4),
+            source.layout(),
+            source.mode(),
+            source.hashes(),
+        )
+        .unwrap();
+
+        assert_eq!(target.hashes(), source.hashes());
+        assert_eq!(target.root(), source.root());
+        assert!((0..target.chunks.chunk_count).all(|c| !target.has_chunk(c)));
+
+        for chunk in 0..source.chunks.chunk_count {
+            let data = source.read_chunk(chunk).unwrap();
+            target.write_chunk(chunk, &data).unwrap();
+            assert_eq!(target.read_chunk(chunk).unwrap(), data);
+        }
+
+        assert!((0..target.chunks.piece_count).all(|p| target.has_piece(p)));
+        assert_eq!(target.completed_pieces(), target.chunks.piece_count);
+        assert!(target.missing_chunks().is_empty());
+        assert_eq!(target.hashes(), source.hashes());
+    }
+
+    #[test]
+    fn test_digest() {
+        let source = StorageMap::<GenericStorage<TestResource>, Sha256>::new(
+            "Source".to_string(),
+            resources("source", 4),
+            &layout(),
+            Mode::Plain,
+        )
+        .unwrap();
+
+        assert_eq!(source.algorithm(), Algorithm::Sha256);
+        assert!(source.hashes().iter().all(|h| h.len() == 32));
+        assert_eq!(source.root().unwrap().len(), 32);
+
+        let result = StorageMap::<GenericStorage<TestResource>, Sha512>::from_hashes(
+            "Target".to_string(),
+            resources("target", 4),
+            source.layout(),
+            source.mode(),
+            source.hashes(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_mode() {
+        let source = TestStorageMap::new(
+            "Source".to_string(),
+            resources("source", 3),
+            &layout(),
+            Mode::Rfc6962,
+        )
+        .unwrap();
+        let plain = TestStorageMap::new(
+            "Plain".to_string(),
+            resources("plain", 3),
+            &layout(),
+            Mode::Plain,
+        )
+        .unwrap();
+
+        assert_eq!(source.mode(), Mode::Rfc6962);
+        assert_ne!(source.hashes(), plain.hashes());
+
+        let mut target = TestStorageMap::from_hashes(
+            "Target".to_string(),
+            resources("target", 3),
+            source.layout(),
+            source.mode(),
+            source.hashes(),
+        )
+        .unwrap();
+
+        for chunk in 0..source.chunks.chunk_count {
+            let data = source.read_chunk(chunk).unwrap();
+            target.write_chunk(chunk, &data).unwrap();
+        }
+
+        assert_eq!(target.completed_pieces(), target.chunks.piece_count);
+        assert_eq!(target.root(), source.root());
+    }
+
+    #[test]
+    fn test_prove_multi() {
+        let source = TestStorageMap::new(
+            "Source".to_string(),
+            resources("source", 5),
+            &layout(),
+            Mode::Plain,
+        )
+        .unwrap();
+        let target = TestStorageMap::from_hashes(
+            "Target".to_string(),
+            resources("target", 5),
+            source.layout(),
+            source.mode(),
+            source.hashes(),
+        )
+        .unwrap();
+
+        let proof = source.prove_multi(&[4, 0, 2]).unwrap();
+        assert_eq!(proof.leaf_indices, vec![0, 2, 4]);
+        target.verify_multi(&proof).unwrap();
+
+        assert!(source.prove_multi(&[5]).is_err());
+    }
+
+    #[test]
+    fn test_prove_range() {
+        let items = vec![
+            ("range_0".to_string(), 10000),
+            ("range_1".to_string(), 30000),
+            ("range_2".to_string(), 5000),
+        ];
+        let map = TestStorageMap::new(
+            "Range".to_string(),
+            items,
+            &Layout::fixed(4096),
+            Mode::Rfc6962,
+        )
+        .unwrap();
+        let root = map.root().unwrap();
+        let piece_size = map.chunks.piece_size;
+
+        let ranges = [
+            (0, 10000),
+            (10000, 30000),
+            (40000, 5000),
+            (4095, 2),
+            (0, 45000),
+        ];
+
+        for (offset, len) in ranges.iter() {
+            let proof = map.prove_range(*offset, *len).unwrap();
+            let data = map.read_storage(*offset, *len).unwrap();
+
+            proof
+                .verify_against_root::<Sha512>(&root, piece_size, *offset, &data, Mode::Rfc6962)
+                .unwrap();
+
+            let mut corrupted = data.clone();
+            corrupted[len / 2] ^= 0xff;
+            assert!(proof
+                .verify_against_root::<Sha512>(
+                    &root,
+                    piece_size,
+                    *offset,
+                    &corrupted,
+                    Mode::Rfc6962
+                )
+                .is_err());
+        }
+
+        // the proven bytes are not the ones requested at another offset,
+        // even if the proof is adjusted to match that offset
+        let data = map.read_storage(4095, 2).unwrap();
+        let mut proof = map.prove_range(4095, 2).unwrap();
+        let moved = |proof: &ByteRangeProof, offset, piece_size| {
+            proof.verify_against_root::<Sha512>(&root, piece_size, offset, &data, Mode::Rfc6962)
+        };
+        assert!(moved(&proof, 4094, piece_size).is_err());
+        assert!(moved(&proof, 4095, piece_size / 2).is_err());
+        let byte = proof.head.pop().unwrap();
+        proof.tail.insert(0, byte);
+        assert!(moved(&proof, 4094, piece_size).is_err());
+
+        for (offset, len) in [(40000, 5001), (usize::MAX, 2)].iter() {
+            match map.prove_range(*offset, *len) {
+                Ok(_) => panic!("Range out of bounds should not have been proven"),
+                Err(error) => match error.kind {
+                    ErrorKind::RangeOutOfBounds(o, l) if o == *offset && l == *len => (),
+                    kind => panic!("Invalid error kind: {:?}", kind),
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let source = TestStorageMap::new(
+            "Source".to_string(),
+            resources("source", 6),
+            &layout(),
+            Mode::Plain,
+        )
+        .unwrap();
+        let mut target = TestStorageMap::from_hashes(
+            "Target".to_string(),
+            resources("target", 6),
+            source.layout(),
+            source.mode(),
+            source.hashes(),
+        )
+        .unwrap();
+
+        assert!(target.diff(&source).unwrap().is_empty());
+
+        for piece in [1, 4].iter() {
+            target.tree.set(*piece, &vec![0; 64]).unwrap();
+        }
+
+        let pieces = target.diff(&source).unwrap();
+        assert_eq!(pieces, vec![1, 4]);
+        assert_eq!(target.piece_chunks(pieces[1]), 16..20);
+    }
+
+    #[test]
+    fn test_save() {
+        use storage::memory::resource::MemoryResource;
+
+        type MemoryStorageMap = StorageMap<GenericStorage<MemoryResource>>;
+
+        let items = |prefix: &str| -> Vec<(String, usize)> {
+            (0..3)
+                .map(|n| (format!("{}_{}", prefix, n), 7000))
+                .collect()
+        };
+
+        let source = TestStorageMap::new(
+            "Source".to_string(),
+            items("saved_source"),
+            &layout(),
+            Mode::Plain,
+        )
+        .unwrap();
+        let mut target = MemoryStorageMap::from_hashes(
+            "Target".to_string(),
+            items("saved_target"),
+            source.layout(),
+            source.mode(),
+            source.hashes(),
+        )
+        .unwrap();
+        target
+            .write_chunk(0, &source.read_chunk(0).unwrap())
+            .unwrap();
+
+        let serialized = bincode::serialize(&target).unwrap();
+        let (root, missing) = (target.root(), target.missing_chunks());
+        drop(target);
+
+        let saved: SavedStorageMap = bincode::deserialize(&serialized).unwrap();
+        let loaded: MemoryStorageMap = saved.open(&LoadOptions::default()).unwrap();
+        assert!(root.is_some());
+        assert_eq!(loaded.root(), root);
+        assert_eq!(missing.len(), 5);
+        assert_eq!(loaded.missing_chunks(), missing);
+
+        items("saved_target").iter().for_each(|(location, _)| {
+            MemoryResource::delete(location).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_trailing_chunks() {
+        let items = |prefix: &str| -> Vec<(String, usize)> {
+            (0..3)
+                .map(|n| (format!("{}_{}", prefix, n), 10000))
+                .collect()
+        };
+
+        let source = TestStorageMap::new(
+            "Source".to_string(),
+            items("source"),
+            &layout(),
+            Mode::Plain,
+        )
+        .unwrap();
+        let mut target = TestStorageMap::from_hashes(
+            "Target".to_string(),
+            items("target"),
+            source.layout(),
+            source.mode(),
+            source.hashes(),
+        )
+        .unwrap();
+
+        let last = target.chunks.chunk_count - 1;
+        assert_eq!(target.chunks.piece_count, 2);
+        assert_eq!(target.chunks.chunk_count, 8);
+        assert_eq!(source.read_chunk(last).unwrap().len(), 30000 - last * 4096);
+        assert!(!target.has_piece(target.chunks.piece_count));
+
+        match target.write_chunk(last, &vec![0; target.chunks.chunk_size]) {
+            Ok(_) => panic!("Chunk of invalid size should not have been accepted"),
+            Err(error) => match error.kind {
+                ErrorKind::ChunkSizeMismatch(1328, 4096) => (),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+
+        match target.write_chunk(last + 1, &vec![]) {
+            Ok(_) => panic!("Chunk out of range should not have been accepted"),
+            Err(error) => match error.kind {
+                ErrorKind::ChunkOutOfRange(8) => (),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+
+        for chunk in 0..source.chunks.chunk_count {
+            let data = source.read_chunk(chunk).unwrap();
+            target.write_chunk(chunk, &data).unwrap();
+        }
+
+        assert!((0..target.chunks.piece_count).all(|p| target.has_piece(p)));
+    }
+
+    #[test]
+    fn test_write_corrupted_piece() {
+        let source = TestStorageMap::new(
+            "Source".to_string(),
+            resources("source", 4),
+            &layout(),
+            Mode::Plain,
+        )
+        .unwrap();
+        let mut target = TestStorageMap::from_hashes(
+            "Target".to_string(),
+            resources("target", 4),
+            source.layout(),
+            source.mode(),
+            source.hashes(),
+        )
+        .unwrap();
+
+        let chunks = target.chunks.piece_chunks(0);
+        let last = chunks.end - 1;
+
+        for chunk in chunks.start..last {
+            let data = source.read_chunk(chunk).unwrap();
+            target.write_chunk(chunk, &data).unwrap();
+        }
+
+        let corrupted = vec![0xff; target.chunks.chunk_size];
+        match target.write_chunk(last, &corrupted) {
+            Ok(_) => panic!("Corrupted piece should not have been accepted"),
+            Err(error) => match error.kind {
+                ErrorKind::PieceHashMismatch(0) => (),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+
+        assert!(!target.has_piece(0));
+        assert!(target.chunks.piece_chunks(0).all(|c| !target.has_chunk(c)));
+
+        for chunk in target.chunks.piece_chunks(0) {
+            let data = source.read_chunk(chunk).unwrap();
+            target.write_chunk(chunk, &data).unwrap();
+        }
+
+        assert!(target.has_piece(0));
+    }
+
+    #[test]
+    fn test_write_read_only() {
+        use storage::error::ErrorKind as StorageErrorKind;
+        use storage::memory::resource::MemoryResource;
+
+        let source = TestStorageMap::new(
+            "Source".to_string(),
+            resources("source", 2),
+            &layout(),
+            Mode::Plain,
+        )
+        .unwrap();
+
+        let items = resources("read_only_map", 2);
+        items.iter().for_each(|(location, size)| {
+            MemoryResource::insert(location, vec![0u8; *size]).unwrap();
+        });
+        let storage = GenericStorage::<MemoryResource>::with_mode(
+            "Target".to_string(),
+            items.clone(),
+            OpenMode::ReadOnly,
+        )
+        .unwrap();
+        let mut target = StorageMap::<_, Sha512>::from_storage_hashes(
+            storage,
+            source.layout(),
+            source.mode(),
+            source.hashes(),
+        )
+        .unwrap();
+
+        let data = source.read_chunk(0).unwrap();
+        match target.write_chunk(0, &data) {
+            Ok(_) => panic!("Read-only map should not have been written"),
+            Err(error) => match error.kind {
+                ErrorKind::StorageError(StorageErrorKind::ReadOnly) => (),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+        assert!(!target.has_chunk(0));
+
+        items.iter().for_each(|(location, _)| {
+            MemoryResource::delete(location).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_from_hashes_piece_count_mismatch() {
+        let source = TestStorageMap::new(
+            "Source".to_string(),
+            resources("source", 4),
+            &layout(),
+            Mode::Plain,
+        )
+        .unwrap();
+        let result = TestStorageMap::from_hashes(
+            "Target".to_string(),
+            resources("target", 2),
+            source.layout(),
+            source.mode(),
+            source.hashes(),
+        );
+
+        match result {
+            Ok(_) => panic!("Map should not have been created"),
+            Err(error) => match error.kind {
+                ErrorKind::PieceCountMismatch(2, 4) => (),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+    }
+
+    #[test]
+    fn test_legacy() {
+        use storage::memory::resource::MemoryResource;
+
+        // Saved by the baseline implementation over a 34816 byte resource
+        let serialized = include_bytes!("../tests/fixtures/legacy_map.bin");
+        let size = 34816;
+        let location = "legacy_map".to_string();
+        let data: Vec<u8> = (0..size).map(|i| (i % 256) as u8).collect();
+        MemoryResource::insert(&location, data).unwrap();
+
+        let saved: LegacySavedStorageMap = bincode::deserialize(&serialized[..]).unwrap();
+        let legacy: StorageMap<GenericStorage<MemoryResource>, Sha512> =
+            saved.open(&LoadOptions::default()).unwrap();
+
+        let items = vec![(location.clone(), size)];
+        let storage = GenericStorage::<MemoryResource>::new("Legacy".to_string(), items).unwrap();
+        let map = StorageMap::<_, Sha512>::from_storage(storage, &layout(), Mode::Plain).unwrap();
+
+        assert_eq!(legacy.name(), "Legacy");
+        assert_eq!(legacy.root(), map.root());
+        assert_eq!(legacy.hashes(), map.hashes());
+        assert_eq!(legacy.mode(), Mode::Plain);
+        assert_eq!(legacy.layout(), map.layout());
+        assert_eq!(legacy.chunks.chunk_count, 9);
+        assert!((0..legacy.chunks.chunk_count).all(|c| legacy.has_chunk(c)));
+        assert_eq!(legacy.read_chunk(8).unwrap(), map.read_chunk(8).unwrap());
+
+        MemoryResource::delete(&location).unwrap();
+    }
+
+    #[test]
+    fn test_legacy_missing() {
+        use storage::error::ErrorKind as StorageErrorKind;
+        use storage::memory::resource::MemoryResource;
+
+        // Replace the single resource of the fixture with three
+        let fixture = include_bytes!("../tests/fixtures/legacy_map.bin");
+        let original = bincode::serialize(&("Legacy", vec!["legacy_map"], 34816usize)).unwrap();
+        assert!(fixture.ends_with(&original));
+
+        let locations: Vec<String> = (0..3).map(|n| format!("legacy_missing_{}", n)).collect();
+        let mut serialized = fixture[..fixture.len() - original.len()].to_vec();
+        let storage = ("Legacy", locations.clone(), 34816usize);
+        serialized.extend(bincode::serialize(&storage).unwrap());
+        MemoryResource::insert(&locations[0], vec![0u8; 16384]).unwrap();
+
+        let saved: LegacySavedStorageMap = bincode::deserialize(&serialized).unwrap();
+        let result: Result<StorageMap<GenericStorage<MemoryResource>, Sha512>, Error> =
+            saved.open(&LoadOptions::default());
+        match result {
+            Ok(_) => panic!("Map should not have been loaded"),
+            Err(error) => match error.kind {
+                ErrorKind::StorageError(StorageErrorKind::InvalidResources(ref invalid)) => {
+                    assert_eq!(
+                        *invalid,
+                        vec![
+                            (locations[1].clone(), 0, None),
+                            (locations[2].clone(), 0, None),
+                        ]
+                    );
+                }
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+
+        MemoryResource::delete(&locations[0]).unwrap();
+    }
+}
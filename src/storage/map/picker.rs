@@ -0,0 +1,163 @@
+use std::ops::Range;
+
+use bit_vec::BitVec;
+use rand::seq::SliceRandom;
+
+use super::chunk::ChunkMap;
+
+/// Order in which missing chunks are requested from peers
+#[derive(Clone, Debug)]
+pub enum Strategy {
+    /// Lowest chunk indices first
+    Sequential,
+    /// Uniformly random chunks
+    Random,
+    /// Chunks held by the fewest peers first; each bitmap holds the chunks
+    /// available at a single peer. Chunks no peer holds are never picked.
+    RarestFirst(Vec<BitVec>),
+}
+
+pub(super) struct Picker<'c> {
+    chunks: &'c ChunkMap,
+}
+
+impl<'c> Picker<'c> {
+    pub fn new(chunks: &'c ChunkMap) -> Self {
+        Picker { chunks }
+    }
+
+    pub fn missing_chunks(&self) -> Vec<usize> {
+        self.chunks
+            .bitmap
+            .iter()
+            .enumerate()
+            .filter(|(_, set)| !set)
+            .map(|(chunk, _)| chunk)
+            .collect()
+    }
+
+    pub fn missing_ranges(&self) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+
+        for chunk in self.missing_chunks() {
+            match ranges.last_mut() {
+                Some(ref mut range) if range.end == chunk => range.end += 1,
+                _ => ranges.push(chunk..chunk + 1),
+            }
+        }
+
+        ranges
+    }
+
+    pub fn completed_pieces(&self) -> usize {
+        (0..self.chunks.piece_count)
+            .filter(|piece| {
+                self.chunks
+                    .piece_chunks(*piece)
+                    .all(|chunk| self.chunks.bitmap.get(chunk).unwrap_or(false))
+            })
+            .count()
+    }
+
+    pub fn pick(&self, count: usize, strategy: &Strategy) -> Vec<usize> {
+        let mut missing = self.missing_chunks();
+
+        match strategy {
+            Strategy::Sequential => {
+                missing.truncate(count);
+                missing
+            }
+            Strategy::Random => {
+                let mut rng = rand::thread_rng();
+                missing.shuffle(&mut rng);
+                missing.truncate(count);
+                missing
+            }
+            Strategy::RarestFirst(peers) => {
+                let mut available: Vec<(usize, usize)> = missing
+                    .into_iter()
+                    .map(|chunk| (Self::availability(peers, chunk), chunk))
+                    .filter(|(peer_count, _)| *peer_count > 0)
+                    .collect();
+
+                available.sort();
+                available
+                    .into_iter()
+                    .take(count)
+                    .map(|(_, chunk)| chunk)
+                    .collect()
+            }
+        }
+    }
+
+    #[inline]
+    fn availability(peers: &[BitVec], chunk: usize) -> usize {
+        peers
+            .iter()
+            .filter(|bitmap| bitmap.get(chunk).unwrap_or(false))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use storage::map::chunk::Layout;
+
+    fn chunk_map(set: &[usize]) -> ChunkMap {
+        let mut chunks = ChunkMap::new(16384, &Layout::fixed(4096), false).unwrap();
+        set.iter().for_each(|chunk| chunks.bitmap.set(*chunk, true));
+        chunks
+    }
+
+    fn peer(chunk_count: usize, set: &[usize]) -> BitVec {
+        let mut bitmap = BitVec::from_elem(chunk_count, false);
+        set.iter().for_each(|chunk| bitmap.set(*chunk, true));
+        bitmap
+    }
+
+    #[test]
+    fn test_missing() {
+        let chunks = chunk_map(&[0, 1, 2, 3, 5, 6, 10, 15]);
+        let picker = Picker::new(&chunks);
+
+        assert_eq!(picker.missing_chunks(), vec![4, 7, 8, 9, 11, 12, 13, 14]);
+        assert_eq!(picker.missing_ranges(), vec![4..5, 7..10, 11..15]);
+        assert_eq!(picker.completed_pieces(), 1);
+    }
+
+    #[test]
+    fn test_pick_sequential() {
+        let chunks = chunk_map(&[0, 2]);
+        let picker = Picker::new(&chunks);
+
+        assert_eq!(picker.pick(3, &Strategy::Sequential), vec![1, 3, 4]);
+        assert_eq!(picker.pick(100, &Strategy::Sequential).len(), 14);
+    }
+
+    #[test]
+    fn test_pick_random() {
+        let chunks = chunk_map(&[0, 2]);
+        let picker = Picker::new(&chunks);
+
+        let mut picked = picker.pick(14, &Strategy::Random);
+        picked.sort();
+        assert_eq!(picked, picker.missing_chunks());
+        assert_eq!(picker.pick(5, &Strategy::Random).len(), 5);
+    }
+
+    #[test]
+    fn test_pick_rarest_first() {
+        let chunks = chunk_map(&[0, 1]);
+        let picker = Picker::new(&chunks);
+        let peers = vec![
+            peer(16, &[0, 1, 2, 3, 4]),
+            peer(16, &[2, 3, 4, 5]),
+            peer(16, &[3, 4, 5, 6]),
+        ];
+
+        let strategy = Strategy::RarestFirst(peers);
+        assert_eq!(picker.pick(3, &strategy), vec![6, 2, 5]);
+        assert_eq!(picker.pick(100, &strategy), vec![6, 2, 5, 3, 4]);
+    }
+}
@@ -0,0 +1,65 @@
+use merkle_tree::digest::Digest;
+use merkle_tree::mode::Mode;
+use merkle_tree::proof::{Leaf, RangeProof};
+use serde::{Deserialize, Serialize};
+
+use super::error::{Error, ErrorKind};
+
+/// Proof of a byte range of a storage. Pieces overlapping the range are
+/// proven as a whole, so the bytes of the boundary pieces lying outside
+/// the range are shipped along with the proof.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ByteRangeProof {
+    /// bytes of the first piece preceding the range
+    pub head: Vec<u8>,
+    /// bytes of the last piece following the range
+    pub tail: Vec<u8>,
+    pub proof: RangeProof,
+}
+
+impl ByteRangeProof {
+    /// Verify raw bytes requested at `offset` against the storage root.
+    /// `piece_size` is the one of the verifier's storage layout, so the
+    /// proof can't place the data at a different offset.
+    pub fn verify_against_root<D>(
+        &self,
+        root: &[u8],
+        piece_size: usize,
+        offset: usize,
+        data: &[u8],
+        mode: Mode,
+    ) -> Result<(), Error>
+    where
+        D: Digest,
+    {
+        let len = data.len();
+        let out_of_bounds = || Err(Error::new(ErrorKind::RangeOutOfBounds(offset, len)));
+
+        let end = match offset.checked_add(len) {
+            Some(end) if len > 0 && piece_size > 0 => end,
+            _ => return out_of_bounds(),
+        };
+        if self.proof.start != offset / piece_size
+            || self.proof.end != (end - 1) / piece_size + 1
+            || self.head.len() != offset % piece_size
+            || self.tail.len() >= piece_size
+        {
+            return out_of_bounds();
+        }
+
+        // only the last piece of the storage may be shorter
+        let size = self.head.len() + len + self.tail.len();
+        if size % piece_size != 0 && self.proof.end != self.proof.leaf_count {
+            return out_of_bounds();
+        }
+
+        let mut bytes = Vec::with_capacity(self.head.len() + data.len() + self.tail.len());
+        bytes.extend_from_slice(&self.head);
+        bytes.extend_from_slice(data);
+        bytes.extend_from_slice(&self.tail);
+
+        let leaves: Vec<Leaf> = bytes.chunks(piece_size).map(Leaf::Data).collect();
+        self.proof.verify_against_root::<D>(root, &leaves, mode)?;
+        Ok(())
+    }
+}
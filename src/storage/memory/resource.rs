@@ -0,0 +1,327 @@
+use std::cmp::min;
+use std::collections::HashMap;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ptr;
+use std::sync::{Arc, Mutex, Once, RwLock};
+
+use storage::error::ErrorKind;
+use storage::resource::{seek_position, PositionalResource, Resource};
+use storage::{Result, Size};
+
+type Buffer = Arc<RwLock<Vec<u8>>>;
+
+/// Process-wide registry of in-memory resources, keyed by location
+fn registry() -> &'static Mutex<HashMap<String, Buffer>> {
+    static INIT: Once = Once::new();
+    static mut REGISTRY: *const Mutex<HashMap<String, Buffer>> = ptr::null();
+
+    // REGISTRY is only written once, before any read, and never freed
+    unsafe {
+        INIT.call_once(|| REGISTRY = Box::into_raw(Box::new(Mutex::new(HashMap::new()))));
+        &*REGISTRY
+    }
+}
+
+fn lookup(location: &str) -> Result<Buffer> {
+    match registry().lock()?.get(location) {
+        Some(buffer) => Ok(buffer.clone()),
+        None => err_new!(ErrorKind::LocationError(location.to_string())),
+    }
+}
+
+fn to_io_error<T>(_: T) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "Memory resource error: lock poisoned")
+}
+
+/// Fixed-size in-memory buffer; writes past its end are truncated
+#[derive(Debug)]
+pub struct MemoryHandle {
+    buffer: Buffer,
+    position: u64,
+}
+
+impl MemoryHandle {
+    fn new(buffer: Buffer) -> Self {
+        MemoryHandle {
+            buffer,
+            position: 0,
+        }
+    }
+
+    fn read_at(&self, offset: usize, into: &mut [u8]) -> io::Result<usize> {
+        let data = self.buffer.read().map_err(to_io_error)?;
+        if offset >= data.len() {
+            return Ok(0);
+        }
+
+        let len = min(into.len(), data.len() - offset);
+        into[..len].copy_from_slice(&data[offset..offset + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: usize, from: &[u8]) -> io::Result<usize> {
+        let mut data = self.buffer.write().map_err(to_io_error)?;
+        if offset >= data.len() {
+            return Ok(0);
+        }
+
+        let len = min(from.len(), data.len() - offset);
+        data[offset..offset + len].copy_from_slice(&from[..len]);
+        Ok(len)
+    }
+}
+
+impl Read for MemoryHandle {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.read_at(self.position as usize, buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Write for MemoryHandle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.write_at(self.position as usize, buf)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemoryHandle {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => seek_position(self.size() as u64, delta),
+            SeekFrom::Current(delta) => seek_position(self.position, delta),
+        };
+
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(io::Error::from(io::ErrorKind::InvalidInput)),
+        }
+    }
+}
+
+impl Size for MemoryHandle {
+    fn size(&self) -> usize {
+        match self.buffer.read() {
+            Ok(data) => data.len(),
+            Err(poisoned) => poisoned.into_inner().len(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryMetadata {
+    size: usize,
+}
+
+impl Size for MemoryMetadata {
+    fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// Resource held in process memory. Resources are registered under their
+/// location, so `open` and `exists` see what was previously created until
+/// the location is deleted. Open resources keep their data once deleted.
+#[derive(Debug)]
+pub struct MemoryResource {
+    memory_handle: MemoryHandle,
+    memory_location: String,
+}
+
+impl MemoryResource {
+    fn new(buffer: Buffer, location: &str) -> Self {
+        MemoryResource {
+            memory_handle: MemoryHandle::new(buffer),
+            memory_location: location.to_string(),
+        }
+    }
+
+    /// Register `data` under `location`, replacing any previous resource
+    pub fn insert(location: &str, data: Vec<u8>) -> Result<Self> {
+        let buffer = Arc::new(RwLock::new(data));
+        registry()
+            .lock()?
+            .insert(location.to_string(), buffer.clone());
+
+        Ok(MemoryResource::new(buffer, location))
+    }
+}
+
+impl Resource for MemoryResource {
+    type Handle = MemoryHandle;
+    type Metadata = MemoryMetadata;
+    type Options = ();
+
+    fn open(location: &String) -> Result<Self> {
+        let buffer = lookup(location)?;
+        Ok(MemoryResource::new(buffer, location))
+    }
+
+    /// Register a zeroed resource of `size` bytes. Existing resources are
+    /// never replaced, as they may be open.
+    fn create(location: &String, size: &usize) -> Result<Self> {
+        let mut registry = registry().lock()?;
+        if registry.contains_key(location) {
+            return err_new!(ErrorKind::AlreadyExists(location.clone()));
+        }
+
+        let buffer = Arc::new(RwLock::new(vec![0u8; *size]));
+        registry.insert(location.clone(), buffer.clone());
+
+        Ok(MemoryResource::new(buffer, location))
+    }
+
+    fn exists(location: &String) -> bool {
+        match registry().lock() {
+            Ok(registry) => registry.contains_key(location),
+            Err(poisoned) => poisoned.into_inner().contains_key(location),
+        }
+    }
+
+    fn metadata(location: &String) -> Result<Self::Metadata> {
+        let size = MemoryHandle::new(lookup(location)?).size();
+        Ok(MemoryMetadata { size })
+    }
+
+    fn delete(location: &str) -> Result<()> {
+        registry().lock()?.remove(location);
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn handle(&mut self) -> &mut Self::Handle {
+        &mut self.memory_handle
+    }
+
+    #[inline(always)]
+    fn location(&self) -> String {
+        self.memory_location.clone()
+    }
+}
+
+impl PositionalResource for MemoryResource {
+    #[inline(always)]
+    fn read_at(&self, offset: usize, into: &mut [u8]) -> io::Result<usize> {
+        self.memory_handle.read_at(offset, into)
+    }
+
+    #[inline(always)]
+    fn write_at(&self, offset: usize, from: &[u8]) -> io::Result<usize> {
+        self.memory_handle.write_at(offset, from)
+    }
+}
+
+impl Clone for MemoryResource {
+    fn clone(&self) -> Self {
+        let buffer = self.memory_handle.buffer.clone();
+        MemoryResource::new(buffer, &self.memory_location)
+    }
+}
+
+impl Size for MemoryResource {
+    #[inline(always)]
+    fn size(&self) -> usize {
+        self.memory_handle.size()
+    }
+}
+
+impl_resource_serde!(MemoryResource);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode;
+    use storage::generic::options::LoadOptions;
+    use storage::generic::resource::PositionalResourcePtr;
+    use storage::generic::{GenericStorage, SavedStorage};
+    use storage::Storage;
+
+    type MemoryStorage = GenericStorage<MemoryResource>;
+    type SharedMemoryStorage =
+        GenericStorage<MemoryResource, PositionalResourcePtr<MemoryResource>>;
+
+    fn items(prefix: &str, sizes: &[usize]) -> Vec<(String, usize)> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(i, size)| (format!("memory://{}/{}", prefix, i), *size))
+            .collect()
+    }
+
+    #[test]
+    fn test_registry() {
+        let location = "memory://test_registry".to_string();
+        assert!(!MemoryResource::exists(&location));
+        assert!(MemoryResource::open(&location).is_err());
+
+        let mut created = MemoryResource::create(&location, &1024).unwrap();
+        created.handle().write_all(&[7u8; 16]).unwrap();
+
+        assert!(MemoryResource::exists(&location));
+        assert_eq!(MemoryResource::metadata(&location).unwrap().size(), 1024);
+
+        let mut opened = MemoryResource::open(&location).unwrap();
+        let mut read = [0u8; 16];
+        opened.handle().read_exact(&mut read).unwrap();
+        assert_eq!(read, [7u8; 16]);
+        assert_eq!(opened.size(), 1024);
+
+        match MemoryResource::create(&location, &512) {
+            Ok(_) => panic!("Existing resource should not have been created"),
+            Err(error) => match error.kind {
+                ErrorKind::AlreadyExists(ref existing) => assert_eq!(*existing, location),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+        assert_eq!(opened.size(), 1024);
+
+        MemoryResource::delete(&location).unwrap();
+        assert!(!MemoryResource::exists(&location));
+        assert_eq!(opened.size(), 1024);
+    }
+
+    #[test]
+    fn test_fixed_size() {
+        let location = "memory://test_fixed_size".to_string();
+        let mut resource = MemoryResource::create(&location, &8).unwrap();
+
+        resource.handle().seek(SeekFrom::Start(6)).unwrap();
+        assert_eq!(resource.handle().write(&[1u8; 4]).unwrap(), 2);
+        assert_eq!(resource.handle().write(&[1u8; 4]).unwrap(), 0);
+        assert_eq!(resource.size(), 8);
+
+        MemoryResource::delete(&location).unwrap();
+    }
+
+    #[test]
+    fn test_storage() {
+        let items = items("test_storage", &[100, 1000, 10]);
+        let storage = MemoryStorage::new("memory".to_string(), items.clone()).unwrap();
+
+        let data: Vec<u8> = (0..1110).map(|n| n as u8).collect();
+        storage.write(0, &data).unwrap();
+
+        let serialized = bincode::serialize(&storage).unwrap();
+        let saved: SavedStorage = bincode::deserialize(&serialized).unwrap();
+        let loaded: SharedMemoryStorage = saved.open(&LoadOptions::default()).unwrap();
+
+        let mut read = vec![0u8; data.len()];
+        loaded.read(0, &mut read).unwrap();
+        assert_eq!(read, data);
+
+        items.iter().for_each(|(location, _)| {
+            MemoryResource::delete(location).unwrap();
+        });
+    }
+}
@@ -5,16 +5,21 @@ pub mod generic;
 #[macro_use]
 pub mod file;
 
+pub mod encrypted;
 pub mod iter;
 pub mod map;
+pub mod memory;
+pub mod relocation;
 pub mod resource;
 pub mod shard;
+pub mod space;
+pub mod tree;
 pub mod view;
 pub(crate) mod tests;
 
 use storage::error::Error;
 use storage::iter::StorageIterator;
-use storage::resource::ResourcePtr;
+use storage::resource::{OpenMode, ResourcePtr};
 
 pub type Result<T> = std::result::Result<T, Error>;
 pub type StorageId = String;
@@ -27,10 +32,20 @@ pub trait Storage: Sized + Size {
     type Ptr: ResourcePtr;
 
     fn new(name: StorageId, items: Vec<(String, usize)>) -> Result<Self>;
+    /// Create a storage of existing or new resources, opened in `mode`.
+    /// Read-only storages never create resources and reject all writes.
+    fn with_mode(name: StorageId, items: Vec<(String, usize)>, mode: OpenMode) -> Result<Self>;
     fn read(&self, offset: usize, into: &mut [u8]) -> Result<usize>;
     fn write(&self, offset: usize, from: &[u8]) -> Result<usize>;
     fn name(&self) -> &StorageId;
 
+    /// Borrow `size` bytes at `offset` without copying. Returns `None` when
+    /// the range spans multiple resources, its resource is not mapped or the
+    /// storage is writable.
+    fn read_slice(&self, _offset: usize, _size: usize) -> Result<Option<&[u8]>> {
+        Ok(None)
+    }
+
     fn iter(&self, chunk_size: usize) -> StorageIterator<Self> {
         StorageIterator::new(self, chunk_size)
     }
@@ -0,0 +1,115 @@
+use std::path::{Path, PathBuf};
+
+/// Mapping of resource locations recorded in a saved storage to the ones
+/// they are opened at on load
+#[derive(Clone, Debug, PartialEq)]
+pub enum Relocation {
+    /// Replace the root of directory tree storages, or the deepest directory
+    /// containing all resources of other storages
+    Root(String),
+    /// Replace the first matching location prefix, given as `(from, to)`
+    /// pairs. Prefixes match whole path components.
+    Rebase(Vec<(String, String)>),
+}
+
+impl Relocation {
+    /// Relocate the root of a directory tree storage
+    pub fn relocate_root(&self, root: &str) -> String {
+        match self {
+            Relocation::Root(new_root) => new_root.clone(),
+            Relocation::Rebase(prefixes) => rebase(prefixes, root),
+        }
+    }
+
+    /// Relocate absolute resource locations
+    pub fn relocate(&self, locations: &[String]) -> Vec<String> {
+        match self {
+            Relocation::Root(new_root) => {
+                let parent = common_parent(locations);
+                let prefixes = [(parent.display().to_string(), new_root.clone())];
+                locations
+                    .iter()
+                    .map(|location| rebase(&prefixes, location))
+                    .collect()
+            }
+            Relocation::Rebase(prefixes) => locations
+                .iter()
+                .map(|location| rebase(prefixes, location))
+                .collect(),
+        }
+    }
+}
+
+fn rebase(prefixes: &[(String, String)], location: &str) -> String {
+    let path = Path::new(location);
+
+    for (from, to) in prefixes.iter() {
+        if let Ok(rest) = path.strip_prefix(from) {
+            return Path::new(to).join(rest).display().to_string();
+        }
+    }
+
+    location.to_string()
+}
+
+fn common_parent(locations: &[String]) -> PathBuf {
+    let mut parents = locations.iter().map(|location| {
+        Path::new(location)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+    });
+
+    let first = match parents.next() {
+        Some(parent) => parent.to_path_buf(),
+        None => return PathBuf::new(),
+    };
+
+    parents.fold(first, |common, parent| {
+        common
+            .components()
+            .zip(parent.components())
+            .take_while(|(left, right)| left == right)
+            .map(|(component, _)| component)
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn test_root() {
+        let relocation = Relocation::Root("/new".to_string());
+        let locations = strings(&["/old/data/a/1", "/old/data/b/2", "/old/data/3"]);
+
+        assert_eq!(relocation.relocate_root("/old/tree"), "/new");
+        assert_eq!(
+            relocation.relocate(&locations),
+            strings(&["/new/a/1", "/new/b/2", "/new/3"])
+        );
+        assert_eq!(
+            relocation.relocate(&strings(&["/old/data/1"])),
+            strings(&["/new/1"])
+        );
+    }
+
+    #[test]
+    fn test_rebase() {
+        let relocation = Relocation::Rebase(vec![
+            ("/old/data".to_string(), "/new".to_string()),
+            ("/old".to_string(), "/other".to_string()),
+        ]);
+        let locations = strings(&["/old/data/1", "/old/database/2", "/elsewhere/3"]);
+
+        assert_eq!(relocation.relocate_root("/old/data/tree"), "/new/tree");
+        assert_eq!(
+            relocation.relocate(&locations),
+            strings(&["/new/1", "/other/database/2", "/elsewhere/3"])
+        );
+    }
+}
@@ -1,23 +1,240 @@
 use std::fmt;
-use std::io::{Read, Seek, Write};
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
 
+use storage::error::ErrorKind;
+use storage::space::Filesystem;
 use storage::{Result, Size};
 
+/// Access mode of resource handles
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OpenMode {
+    #[default]
+    ReadWrite,
+    ReadOnly,
+}
+
 pub trait Resource: Clone + fmt::Debug + Size + Sized {
     type Handle: Read + Seek + Write;
     type Metadata: Size;
+    /// Options resources are opened and created with, e.g. encryption keys
+    type Options: Clone + fmt::Debug + Default;
 
     fn open(location: &String) -> Result<Self>;
+    /// Open the resource with handles in the given mode. Resources without
+    /// read-only handles open read-write; storages still reject writes.
+    fn open_with(location: &String, _mode: OpenMode, _options: &Self::Options) -> Result<Self> {
+        Self::open(location)
+    }
     fn create(location: &String, size: &usize) -> Result<Self>;
+    fn create_with(location: &String, size: &usize, _options: &Self::Options) -> Result<Self> {
+        Self::create(location, size)
+    }
     fn exists(location: &String) -> bool;
     fn metadata(location: &String) -> Result<Self::Metadata>;
+    /// Remove the resource, e.g. when creating a storage fails
+    fn delete(location: &str) -> Result<()>;
+
+    /// Filesystem new resources at the location are allocated on, if any
+    fn filesystem(_location: &str) -> Result<Option<Filesystem>> {
+        Ok(None)
+    }
 
     fn handle(&mut self) -> &mut Self::Handle;
     fn location(&self) -> String;
+
+    /// Borrow the whole resource content, if it is mapped in memory and
+    /// can't be modified through the resource while borrowed
+    fn as_slice(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+/// Resource supporting reads and writes at an offset through a shared
+/// reference, without moving a cursor
+pub trait PositionalResource: Resource {
+    fn read_at(&self, offset: usize, into: &mut [u8]) -> io::Result<usize>;
+    fn write_at(&self, offset: usize, from: &[u8]) -> io::Result<usize>;
 }
 
 pub trait ResourcePtr: Clone + fmt::Debug + Size {
     type Target: Resource;
 
     fn new(r: Self::Target) -> Self;
+
+    /// Borrow the resource content, when possible without locking
+    fn as_slice(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Read until `into` is filled with data starting at `offset`
+    fn read_at(&self, offset: usize, into: &mut [u8]) -> Result<usize>;
+    /// Write all of `from` starting at `offset`
+    fn write_at(&self, offset: usize, from: &[u8]) -> Result<usize>;
+}
+
+/// Resource pointer granting exclusive access to its resource, e.g. by
+/// borrowing or locking it. Pointers sharing positional resources don't.
+pub trait ExclusiveResourcePtr: ResourcePtr {
+    /// Call `f` with exclusive access to the resource
+    fn with_mut<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self::Target) -> Result<T>;
+}
+
+/// `ResourcePtr::read_at` through the handle of an exclusively accessed
+/// resource
+pub(crate) fn read_at_exclusive<P>(ptr: &P, offset: usize, into: &mut [u8]) -> Result<usize>
+where
+    P: ExclusiveResourcePtr,
+{
+    ptr.with_mut(|resource| {
+        seek(resource, offset)?;
+        let handle = resource.handle();
+        read_exact_with(into, |buf, _| handle.read(buf))
+    })
+}
+
+/// `ResourcePtr::write_at` through the handle of an exclusively accessed
+/// resource
+pub(crate) fn write_at_exclusive<P>(ptr: &P, offset: usize, from: &[u8]) -> Result<usize>
+where
+    P: ExclusiveResourcePtr,
+{
+    ptr.with_mut(|resource| {
+        seek(resource, offset)?;
+        let handle = resource.handle();
+        write_all_with(from, |buf, _| handle.write(buf))
+    })
+}
+
+fn seek<R>(resource: &mut R, offset: usize) -> Result<()>
+where
+    R: Resource,
+{
+    let index = resource.handle().seek(SeekFrom::Start(offset as u64))? as usize;
+    if index == offset {
+        return Ok(());
+    }
+
+    err_new!(ErrorKind::InvalidOffset(offset))
+}
+
+/// Call `read` with the unfilled part of `into` and the number of bytes
+/// read so far, until `into` is full. Fails on a premature end of data.
+pub(crate) fn read_exact_with<F>(into: &mut [u8], mut read: F) -> Result<usize>
+where
+    F: FnMut(&mut [u8], usize) -> io::Result<usize>,
+{
+    let mut done = 0;
+
+    while done < into.len() {
+        match read(&mut into[done..], done) {
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            Ok(n) => done += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(done)
+}
+
+/// Call `write` with the unwritten part of `from` and the number of bytes
+/// written so far, until all of `from` is written
+pub(crate) fn write_all_with<F>(from: &[u8], mut write: F) -> Result<usize>
+where
+    F: FnMut(&[u8], usize) -> io::Result<usize>,
+{
+    let mut done = 0;
+
+    while done < from.len() {
+        match write(&from[done..], done) {
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero).into()),
+            Ok(n) => done += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(done)
+}
+
+/// Move a seek `position` by `delta`, or `None` when the result would
+/// overflow or fall before the start
+pub(crate) fn seek_position(position: u64, delta: i64) -> Option<u64> {
+    match delta >= 0 {
+        true => position.checked_add(delta as u64),
+        false => position.checked_sub(delta.wrapping_neg() as u64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use storage::error::ErrorKind;
+
+    #[test]
+    fn test_read_exact_with_short_reads() {
+        let data: Vec<u8> = (0..100u8).collect();
+        let mut into = vec![0u8; 100];
+        let mut calls = 0;
+
+        let read = read_exact_with(&mut into, |buf, done| {
+            calls += 1;
+            if calls % 3 == 0 {
+                return Err(io::Error::from(io::ErrorKind::Interrupted));
+            }
+            let len = std::cmp::min(buf.len(), 7);
+            buf[..len].copy_from_slice(&data[done..done + len]);
+            Ok(len)
+        })
+        .unwrap();
+
+        assert_eq!(read, 100);
+        assert_eq!(into, data);
+    }
+
+    #[test]
+    fn test_read_exact_with_eof() {
+        let mut into = vec![0u8; 16];
+        let result = read_exact_with(&mut into, |buf, done| match done {
+            0 => Ok(buf.len() / 2),
+            _ => Ok(0),
+        });
+
+        match result.unwrap_err().kind {
+            ErrorKind::IoError(ref e) if e.contains("UnexpectedEof") => (),
+            kind => panic!("Invalid error kind: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_write_all_with_short_writes() {
+        let from: Vec<u8> = (0..100u8).collect();
+        let mut written = Vec::new();
+
+        let result = write_all_with(&from, |buf, _| {
+            let len = std::cmp::min(buf.len(), 9);
+            written.extend_from_slice(&buf[..len]);
+            Ok(len)
+        });
+
+        assert_eq!(result.unwrap(), 100);
+        assert_eq!(written, from);
+
+        match write_all_with(&from, |_, _| Ok(0)).unwrap_err().kind {
+            ErrorKind::IoError(ref e) if e.contains("WriteZero") => (),
+            kind => panic!("Invalid error kind: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_seek_position() {
+        assert_eq!(seek_position(10, 5), Some(15));
+        assert_eq!(seek_position(10, -10), Some(0));
+        assert_eq!(seek_position(10, -11), None);
+        assert_eq!(seek_position(u64::MAX, 1), None);
+        assert_eq!(seek_position(u64::MAX, i64::MIN), Some((1 << 63) - 1));
+    }
 }
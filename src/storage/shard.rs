@@ -47,7 +47,7 @@ pub trait Sharded: Storage {
 pub trait ShardReader: Storage {
     fn read_shard(
         &self,
-        resource: &mut <<Self as Storage>::Ptr as ResourcePtr>::Target,
+        resource: &<Self as Storage>::Ptr,
         shard: &Shard,
         into: &mut [u8],
     ) -> Result<usize>;
@@ -56,7 +56,7 @@ pub trait ShardReader: Storage {
 pub trait ShardWriter: Storage {
     fn write_shard(
         &self,
-        resource: &mut <<Self as Storage>::Ptr as ResourcePtr>::Target,
+        resource: &<Self as Storage>::Ptr,
         shard: &Shard,
         from: &[u8],
     ) -> Result<usize>;
@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::Path;
+
+use fs2;
+
+use storage::error::Error;
+use storage::Result;
+
+/// Maximum number of bytes storages may allocate for new resources
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quota(pub usize);
+
+/// Filesystem a location belongs to, with the space available on it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Filesystem {
+    /// Identifies the filesystem among others on this machine
+    pub id: u64,
+    pub available: u64,
+}
+
+impl Filesystem {
+    /// Filesystem of the deepest existing directory or file on the path to
+    /// `location`, which itself may not exist yet
+    pub fn of(location: &str) -> Result<Self> {
+        let existing = Path::new(location)
+            .ancestors()
+            .map(|path| match path.as_os_str().is_empty() {
+                true => Path::new("."),
+                false => path,
+            })
+            .find(|path| path.exists())
+            .ok_or_else(|| Error::from(Path::new(location)))?;
+
+        Ok(Filesystem {
+            id: device_id(existing)?,
+            available: fs2::available_space(existing)?,
+        })
+    }
+}
+
+#[cfg(unix)]
+fn device_id(path: &Path) -> Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(fs::metadata(path)?.dev())
+}
+
+/// Without device ids, all locations are assumed to share one filesystem,
+/// which overestimates the space required on each
+#[cfg(not(unix))]
+fn device_id(path: &Path) -> Result<u64> {
+    fs::metadata(path)?;
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filesystem() {
+        let dir = std::env::temp_dir();
+        let missing = dir.join("golem-space-missing").join("a").join("b");
+
+        let filesystem = Filesystem::of(&dir.display().to_string()).unwrap();
+        assert_eq!(
+            Filesystem::of(&missing.display().to_string()).unwrap().id,
+            filesystem.id
+        );
+        assert!(Filesystem::of("relative-missing").is_ok());
+    }
+}
@@ -35,6 +35,7 @@ impl TestResource {
 impl Resource for TestResource {
     type Handle = TestHandle;
     type Metadata = String;
+    type Options = ();
 
     fn open(location: &String) -> Result<Self> {
         let size = 65536 as usize;
@@ -53,6 +54,10 @@ impl Resource for TestResource {
         Ok("Metadata".to_string())
     }
 
+    fn delete(_location: &str) -> Result<()> {
+        Ok(())
+    }
+
     fn handle(&mut self) -> &mut Self::Handle {
         &mut self.test_handle
     }
@@ -0,0 +1,393 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use storage::error::ErrorKind;
+use storage::Result;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EntryKind {
+    File(usize),
+    Dir,
+}
+
+/// File or directory at a `/`-separated path, relative to the tree root
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub path: String,
+    pub kind: EntryKind,
+    pub mode: u32,
+}
+
+/// Directory tree recorded relative to its root, including empty
+/// directories and permission modes. Entries are ordered depth-first, with
+/// siblings sorted by name, so walking equal trees gives equal results.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DirTree {
+    pub entries: Vec<Entry>,
+}
+
+impl DirTree {
+    /// Walk the directory at `root` recursively. Symbolic links are not
+    /// followed and are rejected.
+    pub fn walk(root: &str) -> Result<Self> {
+        let mut tree = DirTree::default();
+        tree.walk_dir(Path::new(root), "")?;
+        Ok(tree)
+    }
+
+    fn walk_dir(&mut self, dir: &Path, prefix: &str) -> Result<()> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            match entry.file_name().into_string() {
+                Ok(name) => names.push(name),
+                Err(_) => return Err(entry.path().as_path().into()),
+            }
+        }
+        names.sort();
+
+        for name in names {
+            let path = dir.join(&name);
+            let relative = match prefix {
+                "" => name,
+                _ => format!("{}/{}", prefix, name),
+            };
+
+            let metadata = fs::symlink_metadata(&path)?;
+            let kind = if metadata.is_dir() {
+                EntryKind::Dir
+            } else if metadata.is_file() {
+                EntryKind::File(metadata.len() as usize)
+            } else {
+                return Err(path.as_path().into());
+            };
+
+            self.entries.push(Entry {
+                path: relative.clone(),
+                kind,
+                mode: mode_of(&metadata),
+            });
+
+            if kind == EntryKind::Dir {
+                self.walk_dir(&path, &relative)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Relative paths and sizes of the files in the tree
+    pub fn items(&self) -> Vec<(String, usize)> {
+        self.entries
+            .iter()
+            .filter_map(|entry| match entry.kind {
+                EntryKind::File(size) => Some((entry.path.clone(), size)),
+                EntryKind::Dir => None,
+            })
+            .collect()
+    }
+
+    /// Check that every path stays within the tree root
+    pub fn validate(&self) -> Result<()> {
+        self.entries
+            .iter()
+            .try_for_each(|entry| validate_path(&entry.path))
+    }
+
+    /// Create every directory of the tree under `root`, recording ones
+    /// which didn't exist before in `created`
+    pub fn create_dirs(&self, root: &str, created: &mut Vec<PathBuf>) -> Result<()> {
+        create_dir_all(Path::new(root), created)?;
+
+        self.entries
+            .iter()
+            .filter(|entry| entry.kind == EntryKind::Dir)
+            .try_for_each(|entry| create_dir_all(Path::new(&join(root, &entry.path)), created))
+    }
+
+    /// Apply recorded modes under `root`. Directories are handled last and
+    /// deepest first, so restrictive modes don't block later changes.
+    pub fn apply_modes(&self, root: &str) -> Result<()> {
+        let (dirs, files): (Vec<&Entry>, Vec<&Entry>) = self
+            .entries
+            .iter()
+            .partition(|entry| entry.kind == EntryKind::Dir);
+
+        files
+            .into_iter()
+            .chain(dirs.into_iter().rev())
+            .try_for_each(|entry| set_mode(&join(root, &entry.path), entry.mode))
+    }
+}
+
+/// Location of a `/`-separated relative `path` under `root`
+pub fn join(root: &str, path: &str) -> String {
+    path.split('/')
+        .fold(PathBuf::from(root), |joined, component| {
+            joined.join(component)
+        })
+        .display()
+        .to_string()
+}
+
+/// Directories on the path to `path`, including itself, which don't exist
+/// yet, outermost first
+pub fn missing_dirs(path: &Path) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = path
+        .ancestors()
+        .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+        .map(Path::to_path_buf)
+        .collect();
+    missing.reverse();
+    missing
+}
+
+/// Remove directories recorded as created, deepest first. Directories
+/// which aren't empty are kept.
+pub fn remove_dirs(created: &[PathBuf]) {
+    created.iter().rev().for_each(|dir| {
+        let _ = fs::remove_dir(dir);
+    });
+}
+
+/// Create `path` with its parents, recording the directories created
+fn create_dir_all(path: &Path, created: &mut Vec<PathBuf>) -> Result<()> {
+    let missing = missing_dirs(path);
+    let result = fs::create_dir_all(path);
+    created.extend(missing.into_iter().filter(|dir| dir.is_dir()));
+    result?;
+    Ok(())
+}
+
+fn validate_path(path: &str) -> Result<()> {
+    let valid = path.split('/').all(|component| {
+        !component.is_empty() && component != "." && component != ".." && !component.contains('\\')
+    });
+
+    match valid {
+        true => Ok(()),
+        false => err_new!(ErrorKind::LocationError(path.to_string())),
+    }
+}
+
+#[cfg(unix)]
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    match metadata.permissions().readonly() {
+        true => 0o555,
+        false => 0o755,
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(location: &str, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(location, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_mode(location: &str, mode: u32) -> Result<()> {
+    let mut permissions = fs::metadata(location)?.permissions();
+    permissions.set_readonly(mode & 0o200 == 0);
+    fs::set_permissions(location, permissions)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode;
+    use storage::file::resource::FileResource;
+    use storage::generic::options::{CreateOptions, LoadOptions};
+    use storage::generic::{GenericStorage, SavedStorage};
+    use storage::relocation::Relocation;
+    use storage::{Size, Storage};
+
+    type FileStorage = GenericStorage<FileResource>;
+
+    fn temp_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("golem-tree-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.display().to_string()
+    }
+
+    fn make_source(root: &str) {
+        fs::create_dir_all(join(root, "b/empty")).unwrap();
+        fs::create_dir_all(join(root, "a")).unwrap();
+        fs::write(join(root, "b/data.bin"), vec![7u8; 3000]).unwrap();
+        fs::write(join(root, "a/z.txt"), b"z").unwrap();
+        fs::write(join(root, "c.txt"), b"").unwrap();
+        fs::write(join(root, "a/y.txt"), b"yy").unwrap();
+        set_mode(&join(root, "a/z.txt"), 0o600).unwrap();
+        set_mode(&join(root, "b/empty"), 0o700).unwrap();
+    }
+
+    #[test]
+    fn test_validate() {
+        let valid = ["a", "a/b", "a/b.c", "..a/b"];
+        let invalid = ["", "/a", "a/../b", "..", "./a", "a//b", "a\\b"];
+
+        for path in valid.iter() {
+            assert!(validate_path(path).is_ok(), "{}", path);
+        }
+        for path in invalid.iter() {
+            match validate_path(path) {
+                Ok(_) => panic!("Path {:?} should be invalid", path),
+                Err(error) => match error.kind {
+                    ErrorKind::LocationError(_) => (),
+                    kind => panic!("Invalid error kind: {:?}", kind),
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn test_walk() {
+        let root = temp_dir("walk");
+        make_source(&root);
+
+        let tree = DirTree::walk(&root).unwrap();
+        let paths: Vec<&str> = tree.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "a",
+                "a/y.txt",
+                "a/z.txt",
+                "b",
+                "b/data.bin",
+                "b/empty",
+                "c.txt"
+            ]
+        );
+        assert_eq!(
+            tree.items(),
+            vec![
+                ("a/y.txt".to_string(), 2),
+                ("a/z.txt".to_string(), 1),
+                ("b/data.bin".to_string(), 3000),
+                ("c.txt".to_string(), 0),
+            ]
+        );
+        assert_eq!(tree, DirTree::walk(&root).unwrap());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source_root = temp_dir("source");
+        let dest_root = temp_dir("dest");
+        let moved_root = temp_dir("moved");
+        make_source(&source_root);
+
+        let options = CreateOptions::default();
+        let source = FileStorage::from_dir("tree".to_string(), &source_root, &options).unwrap();
+        let tree = source.tree().unwrap().clone();
+        let dest = FileStorage::from_tree("tree".to_string(), &dest_root, tree, &options).unwrap();
+        assert_eq!(dest.root(), Some(&dest_root));
+
+        let mut data = vec![0u8; source.size()];
+        source.read(0, &mut data).unwrap();
+        dest.write(0, &data).unwrap();
+        dest.apply_modes().unwrap();
+        assert_eq!(DirTree::walk(&dest_root).unwrap(), *source.tree().unwrap());
+
+        let serialized = bincode::serialize(&dest).unwrap();
+        drop(dest);
+        fs::rename(&dest_root, &moved_root).unwrap();
+
+        let options = LoadOptions {
+            relocation: Some(Relocation::Root(moved_root.clone())),
+            ..LoadOptions::default()
+        };
+        let saved: SavedStorage = bincode::deserialize(&serialized).unwrap();
+        let moved: FileStorage = saved.open(&options).unwrap();
+        assert_eq!(moved.root(), Some(&moved_root));
+
+        let mut read = vec![0u8; data.len()];
+        moved.read(0, &mut read).unwrap();
+        assert_eq!(read, data);
+
+        fs::remove_dir_all(&source_root).unwrap();
+        fs::remove_dir_all(&moved_root).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_tree() {
+        let root = temp_dir("invalid");
+        let tree = DirTree {
+            entries: vec![Entry {
+                path: "../escape".to_string(),
+                kind: EntryKind::File(1),
+                mode: 0o644,
+            }],
+        };
+
+        let options = CreateOptions::default();
+        match FileStorage::from_tree("tree".to_string(), &root, tree, &options) {
+            Ok(_) => panic!("Storage should not have been created"),
+            Err(error) => match error.kind {
+                ErrorKind::LocationError(_) => (),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+        assert!(!Path::new(&root).exists());
+    }
+
+    #[test]
+    fn test_rollback() {
+        use storage::space::Quota;
+
+        let root = temp_dir("rollback");
+        let entry = |path: &str, kind| Entry {
+            path: path.to_string(),
+            kind,
+            mode: 0o755,
+        };
+        let tree = DirTree {
+            entries: vec![
+                entry("a", EntryKind::Dir),
+                entry("a/b", EntryKind::Dir),
+                entry("a/b/f", EntryKind::File(10)),
+                entry("z", EntryKind::File(10)),
+            ],
+        };
+
+        let options = CreateOptions {
+            quota: Some(Quota(10)),
+            ..CreateOptions::default()
+        };
+        match FileStorage::from_tree("tree".to_string(), &root, tree.clone(), &options) {
+            Ok(_) => panic!("Storage should not have been created"),
+            Err(error) => match error.kind {
+                ErrorKind::QuotaExceeded(20, 10) => (),
+                kind => panic!("Invalid error kind: {:?}", kind),
+            },
+        }
+        assert!(!Path::new(&root).exists());
+
+        // "z" exists with another size, after "a/b/f" was created
+        fs::create_dir_all(&root).unwrap();
+        fs::write(join(&root, "z"), b"z").unwrap();
+        let options = CreateOptions::default();
+        assert!(FileStorage::from_tree("tree".to_string(), &root, tree, &options).is_err());
+        assert!(!Path::new(&join(&root, "a")).exists());
+
+        // Parents of "p/q/f" are created by the resource, before "z/x" fails
+        let items = vec![(join(&root, "p/q/f"), 10), (join(&root, "z/x"), 10)];
+        assert!(FileStorage::new("files".to_string(), items).is_err());
+        assert!(!Path::new(&join(&root, "p")).exists());
+        assert!(Path::new(&join(&root, "z")).exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
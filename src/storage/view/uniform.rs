@@ -56,7 +56,7 @@ where
         }
 
         let start = self.start + self.consumed - offset;
-        let consumed = min(size, self.size() - self.consumed) - start;
+        let consumed = min(size - start, self.size() - self.consumed);
 
         let pointer = P::clone(pointer);
         let shard = Shard {
@@ -136,6 +136,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_build_inner() {
+        let mut uniform = UniformView::<Ptr>::new(256, 768);
+        resources().iter().all(|resource| uniform.add(resource));
+
+        let view = uniform.build().unwrap();
+        assert_eq!(view.len(), 1);
+        assert_eq!(
+            view[0].1,
+            Shard {
+                start: 256,
+                end: 768
+            }
+        );
+    }
+
     #[test]
     fn test_build() {
         let mut uniform = UniformView::<Ptr>::new(1, 2047);